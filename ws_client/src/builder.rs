@@ -0,0 +1,433 @@
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::client::{dial, dial_proxy, handshake, WSClient};
+use crate::errors::ClientError;
+use crate::proxy::Proxy;
+use ws_core::conn_info::ConnectionInfo;
+use ws_core::http_utils::HeadLimits;
+use ws_core::keepalive::KeepAlive;
+#[cfg(feature = "rustls")]
+use ws_core::rustls::ClientConfig;
+use ws_core::socket::SocketOptions;
+use ws_core::{ConnectionStatus, WSHandler, WSStream};
+
+/// What goes into the upgrade request besides the URL
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestOptions {
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) origin: Option<String>,
+    pub(crate) protocols: Vec<String>,
+    pub(crate) extensions: Vec<String>,
+}
+
+/// Headers only meant for the host they were set for
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+impl RequestOptions {
+    /// Drops the credentials before the request is sent on to another host or port
+    fn strip_credentials(&mut self) {
+        self.headers.retain(|(name, _)| {
+            !CREDENTIAL_HEADERS
+                .iter()
+                .any(|c| name.eq_ignore_ascii_case(c))
+        });
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+enum ProxyMode {
+    #[default]
+    Direct,
+    Proxy(Proxy),
+    FromEnv,
+}
+
+/// Which redirects of the upgrade request are followed
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RedirectPolicy {
+    /// ws:// to ws:// and wss:// to wss:// only
+    #[default]
+    SameScheme,
+    /// Also ws:// to wss://, no policy allows the other way
+    AllowUpgrade,
+}
+
+/// Configures a client connection, e.g.
+/// `WSClientBuilder::new("wss://api.example.com/feed").header("Authorization", "Bearer ..")`
+#[derive(Clone)]
+pub struct WSClientBuilder {
+    url: String,
+    request: RequestOptions,
+    connect_timeout: Option<Duration>,
+    socket: SocketOptions,
+    handshake_timeout: Option<Duration>,
+    head_limits: HeadLimits,
+    read_timeout: Option<Duration>,
+    max_frame_size: usize,
+    max_message_size: usize,
+    keepalive: Option<KeepAlive>,
+    proxy: ProxyMode,
+    max_redirects: u32,
+    redirect_policy: RedirectPolicy,
+    #[cfg(feature = "rustls")]
+    tls: Option<Arc<ClientConfig>>,
+}
+
+impl WSClientBuilder {
+    pub fn new(url: &str) -> Self {
+        WSClientBuilder {
+            url: url.to_string(),
+            request: RequestOptions::default(),
+            connect_timeout: None,
+            socket: SocketOptions::default(),
+            handshake_timeout: None,
+            head_limits: HeadLimits::default(),
+            read_timeout: None,
+            max_frame_size: 0,
+            max_message_size: 0,
+            keepalive: None,
+            proxy: ProxyMode::Direct,
+            max_redirects: 0,
+            redirect_policy: RedirectPolicy::SameScheme,
+            #[cfg(feature = "rustls")]
+            tls: None,
+        }
+    }
+
+    /// Adds a header to the upgrade request, like `Authorization` or `Cookie`
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request
+            .headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.request.origin = Some(origin.to_string());
+        self
+    }
+
+    /// Offers a subprotocol, in order of preference
+    pub fn protocol(mut self, protocol: &str) -> Self {
+        self.request.protocols.push(protocol.to_string());
+        self
+    }
+
+    /// Offers an extension with its parameters, e.g. `permessage-deflate; client_max_window_bits`
+    pub fn extension(mut self, extension: &str) -> Self {
+        self.request.extensions.push(extension.to_string());
+        self
+    }
+
+    /// Time allowed to open the TCP connection to each resolved address
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set on the TCP socket before connecting, to the proxy when there is one
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    /// Time allowed for writing the upgrade request and reading the response, TLS included
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Largest upgrade response head accepted, 16 KiB by default
+    pub fn max_header_size(mut self, size: usize) -> Self {
+        self.head_limits.max_size = size;
+        self
+    }
+
+    /// See `WSStream::set_read_timeout`
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// See `WSStream::set_max_frame_size`
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// See `WSStream::set_max_message_size`
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// See `WSStream::set_keepalive`
+    pub fn keepalive(mut self, keepalive: KeepAlive) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Connects through `proxy`, see `Proxy::parse`
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = ProxyMode::Proxy(proxy);
+        self
+    }
+
+    /// Connects through the proxy set in the environment, see `Proxy::from_env`
+    pub fn proxy_from_env(mut self) -> Self {
+        self.proxy = ProxyMode::FromEnv;
+        self
+    }
+
+    /// Follows up to `max` 301, 302, 307 and 308 responses to the upgrade request to their
+    /// `Location`, none by default. `Authorization`, `Cookie` and `Proxy-Authorization` are
+    /// left out once a redirect leads to another host or port.
+    pub fn follow_redirects(mut self, max: u32, policy: RedirectPolicy) -> Self {
+        self.max_redirects = max;
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Verifies wss:// servers with `config` instead of the Mozilla root store
+    #[cfg(feature = "rustls")]
+    pub fn tls_config(mut self, config: Arc<ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    pub fn connect<H>(&self, handler: H) -> Result<WSClient<H>, ClientError>
+    where
+        H: WSHandler,
+    {
+        self.connect_with(Arc::new(handler))
+    }
+
+    /// Same as `connect` sharing `handler` with other connections
+    pub(crate) fn connect_with<H>(&self, handler: Arc<H>) -> Result<WSClient<H>, ClientError>
+    where
+        H: WSHandler,
+    {
+        let mut host_uri = match Url::parse(&self.url) {
+            Ok(uri) => uri,
+            Err(_) => return Err(ClientError::Connection("Invalid host url".to_string())),
+        };
+
+        self.validate_headers()?;
+
+        let mut request = self.request.clone();
+        let mut redirects = 0;
+        let mut ws_stream = loop {
+            let e = match self.upgrade(&host_uri, &request, handler.clone()) {
+                Ok(s) => break s,
+                Err(ClientError::Handshake(e)) => e,
+                Err(e) => return Err(e),
+            };
+
+            let location = match (e.status, e.headers.get("location")) {
+                (301 | 302 | 307 | 308, Some(l)) => l.to_string(),
+                _ => return Err(ClientError::Handshake(e)),
+            };
+
+            if redirects >= self.max_redirects {
+                return Err(match self.max_redirects {
+                    0 => ClientError::Handshake(e),
+                    max => ClientError::Connection(format!("More than {} redirects", max)),
+                });
+            }
+            redirects += 1;
+
+            let next = redirect_target(&host_uri, &location, self.redirect_policy)?;
+            println!("Redirected to {}", next);
+            if next.host_str() != host_uri.host_str()
+                || next.port_or_known_default() != host_uri.port_or_known_default()
+            {
+                request.strip_credentials();
+            }
+            host_uri = next;
+        };
+
+        set_timeouts(&ws_stream.stream, None)?;
+        ws_stream.set_read_timeout(self.read_timeout)?;
+        ws_stream.set_max_frame_size(self.max_frame_size);
+        ws_stream.set_max_message_size(self.max_message_size);
+        if self.keepalive.is_some() {
+            ws_stream.set_keepalive(self.keepalive)?;
+        }
+
+        Ok(WSClient {
+            ws_state: ConnectionStatus::Open,
+            ws_stream,
+        })
+    }
+
+    /// Connects to `host_uri` and runs the upgrade with `request`
+    fn upgrade<H>(
+        &self,
+        host_uri: &Url,
+        request: &RequestOptions,
+        handler: Arc<H>,
+    ) -> Result<WSStream<H>, ClientError>
+    where
+        H: WSHandler,
+    {
+        let proxy = match &self.proxy {
+            ProxyMode::Direct => None,
+            ProxyMode::Proxy(p) => Some(p.clone()),
+            ProxyMode::FromEnv => Proxy::from_env(host_uri)?,
+        };
+
+        let mut tcp_stream = match &proxy {
+            Some(p) => dial_proxy(host_uri, p, self.connect_timeout, &self.socket)?,
+            None => dial(host_uri, self.connect_timeout, &self.socket)?,
+        };
+        set_timeouts(&tcp_stream, self.handshake_timeout)?;
+        let deadline = self.handshake_timeout.map(|t| Instant::now() + t);
+
+        if host_uri.scheme() == "wss" {
+            self.connect_tls(host_uri, tcp_stream, request, handler, deadline)
+        } else {
+            let socket = match tcp_stream.try_clone() {
+                Ok(s) => s,
+                Err(e) => return Err(e.to_string().into()),
+            };
+            let (negotiated, rest) = handshake(
+                host_uri,
+                &mut tcp_stream,
+                &socket,
+                request,
+                &self.head_limits,
+                deadline,
+            )?;
+            let ws_stream = WSStream::new(tcp_stream, handler).with_buffered(rest);
+            let info = ConnectionInfo {
+                protocol: negotiated.protocol,
+                extensions: negotiated.extensions,
+                ..ws_stream.info().clone()
+            };
+            Ok(ws_stream.with_info(info))
+        }
+    }
+
+    #[cfg(feature = "rustls")]
+    fn connect_tls<H>(
+        &self,
+        host: &Url,
+        stream: TcpStream,
+        request: &RequestOptions,
+        handler: Arc<H>,
+        deadline: Option<Instant>,
+    ) -> Result<WSStream<H>, ClientError>
+    where
+        H: WSHandler,
+    {
+        let config = match &self.tls {
+            Some(c) => c.clone(),
+            None => crate::tls::default_client_config()?,
+        };
+        crate::tls::connect(
+            host,
+            stream,
+            config,
+            handler,
+            request,
+            &self.head_limits,
+            deadline,
+        )
+    }
+
+    #[cfg(not(feature = "rustls"))]
+    fn connect_tls<H>(
+        &self,
+        _host: &Url,
+        _stream: TcpStream,
+        _request: &RequestOptions,
+        _handler: Arc<H>,
+        _deadline: Option<Instant>,
+    ) -> Result<WSStream<H>, ClientError>
+    where
+        H: WSHandler,
+    {
+        Err(ClientError::Connection(
+            "wss:// needs the rustls feature".to_string(),
+        ))
+    }
+
+    /// Header names and values ending up on their own line would let a value inject headers
+    fn validate_headers(&self) -> Result<(), ClientError> {
+        let values = self
+            .request
+            .headers
+            .iter()
+            .flat_map(|(n, v)| [n, v])
+            .chain(self.request.origin.iter())
+            .chain(self.request.protocols.iter())
+            .chain(self.request.extensions.iter());
+
+        for value in values {
+            if value.contains(['\r', '\n']) {
+                return Err(ClientError::Connection(format!(
+                    "Invalid header value {:?}",
+                    value
+                )));
+            }
+        }
+
+        for (name, _) in &self.request.headers {
+            if name.is_empty() || name.contains([':', ' ']) {
+                return Err(ClientError::Connection(format!(
+                    "Invalid header name {:?}",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set_timeouts(stream: &TcpStream, timeout: Option<Duration>) -> Result<(), String> {
+    if let Err(e) = stream.set_read_timeout(timeout) {
+        return Err(e.to_string());
+    }
+    if let Err(e) = stream.set_write_timeout(timeout) {
+        return Err(e.to_string());
+    }
+    Ok(())
+}
+
+/// Resolves `location` against the URL that was redirected, http:// and https:// locations
+/// stand for ws:// and wss://
+pub(crate) fn redirect_target(
+    from: &Url,
+    location: &str,
+    policy: RedirectPolicy,
+) -> Result<Url, String> {
+    let mut target = match from.join(location) {
+        Ok(u) => u,
+        Err(_) => return Err(format!("Invalid redirect location {}", location)),
+    };
+
+    let scheme = match target.scheme() {
+        "http" | "ws" => "ws",
+        "https" | "wss" => "wss",
+        s => return Err(format!("Redirect to unsupported scheme {}", s)),
+    };
+    if target.scheme() != scheme {
+        // http and ws are both special schemes, so the scheme can be swapped
+        let _ = target.set_scheme(scheme);
+    }
+
+    let allowed = match (policy, from.scheme(), scheme) {
+        (_, a, b) if a == b => true,
+        // the request and its credentials would go out in the clear
+        (_, "wss", "ws") => false,
+        (RedirectPolicy::AllowUpgrade, "ws", "wss") => true,
+        _ => false,
+    };
+    if !allowed {
+        return Err(format!("Redirect from {} to {} not allowed", from, target));
+    }
+    Ok(target)
+}
@@ -0,0 +1,286 @@
+#[cfg(test)]
+mod tests {
+    use crate::builder::{redirect_target, RedirectPolicy, WSClientBuilder};
+    use crate::errors::ClientError;
+    use crate::test_common::{accept_upgrade, TestHandler};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use url::Url;
+    use ws_core::data_frame_tx::{Agent, DataFrame, FrameType};
+    use ws_core::http_utils::{parse_request, HeadLimits, Headers};
+    use ws_core::{base64, sha1, WSHandler};
+
+    struct Recorder {
+        messages: Mutex<Sender<String>>,
+    }
+
+    impl WSHandler for Recorder {
+        fn handle_text_msg(&self, msg: String) {
+            self.messages.lock().unwrap().send(msg).unwrap();
+        }
+
+        fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+
+        fn who(&self) -> Agent {
+            Agent::Client
+        }
+    }
+
+    /// Accepts one upgrade, answering with `extra` response headers, and hands back the request
+    fn fake_server(extra: &'static str) -> (String, Receiver<Headers>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "ws://127.0.0.1:{}/feed",
+            listener.local_addr().unwrap().port()
+        );
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            tx.send(accept_upgrade(&mut stream, extra)).unwrap();
+            let _ = stream.read(&mut [0u8; 512]);
+        });
+
+        (url, rx)
+    }
+
+    #[test]
+    fn test_custom_request_headers() {
+        let (url, rx) = fake_server("Sec-WebSocket-Protocol: graphql-transport-ws\r\n");
+
+        let client = WSClientBuilder::new(&url)
+            .header("Authorization", "Bearer abc.def")
+            .header("X-Request-Id", "42")
+            .origin("https://app.example.com")
+            .protocol("graphql-transport-ws")
+            .protocol("custom.v1")
+            .extension("permessage-deflate; client_max_window_bits")
+            .connect(TestHandler);
+        assert!(client.is_ok());
+
+        let headers = rx.recv().unwrap();
+        assert_eq!(&headers["authorization"], "Bearer abc.def");
+        assert_eq!(&headers["x-request-id"], "42");
+        assert_eq!(&headers["origin"], "https://app.example.com");
+        assert_eq!(
+            &headers["sec-websocket-protocol"],
+            "graphql-transport-ws, custom.v1"
+        );
+        assert_eq!(
+            &headers["sec-websocket-extensions"],
+            "permessage-deflate; client_max_window_bits"
+        );
+    }
+
+    #[test]
+    fn test_header_injection_is_rejected() {
+        let res = WSClientBuilder::new("ws://127.0.0.1:1/")
+            .header("X-Id", "1\r\nX-Admin: true")
+            .connect(TestHandler);
+
+        match res {
+            Err(ClientError::Connection(e)) => assert!(e.starts_with("Invalid header value")),
+            _ => panic!("expected the header to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        // accepts but answers the upgrade a byte every 50 ms until the test is over, each read
+        // alone would not time out
+        let (done, done_rx) = channel::<()>();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 2048]);
+            for b in b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n" {
+                let over = done_rx.recv_timeout(Duration::from_millis(50));
+                if over != Err(RecvTimeoutError::Timeout) || stream.write_all(&[*b]).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let started = Instant::now();
+        let res = WSClientBuilder::new(&url)
+            .handshake_timeout(Duration::from_millis(300))
+            .connect(TestHandler);
+        match res {
+            Err(ClientError::Connection(e)) => assert_eq!(e, "Timed out reading HTTP head"),
+            _ => panic!("expected the handshake to time out"),
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        drop(done);
+    }
+
+    #[test]
+    fn test_max_header_size() {
+        let (url, _) =
+            fake_server("X-Padding: xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx\r\n");
+
+        let res = WSClientBuilder::new(&url)
+            .max_header_size(128)
+            .connect(TestHandler);
+        match res {
+            Err(ClientError::Handshake(e)) => {
+                assert_eq!(e.status, 0);
+                assert_eq!(e.message, "HTTP head larger than 128 bytes");
+            }
+            _ => panic!("expected the response to be too large"),
+        }
+    }
+
+    #[test]
+    fn test_split_response_and_early_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 2048];
+            let n = stream.read(&mut buf).unwrap();
+            let headers = parse_request(&buf[..n], &HeadLimits::default())
+                .unwrap()
+                .0
+                .headers;
+            let key = headers["sec-websocket-key"].to_string();
+            let accept =
+                base64::encode(&sha1::hash(&(key + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11")));
+
+            // heads are read 1 KiB at a time, so the padding makes it take several reads however
+            // the pieces arrive
+            let start = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nX-Padding: {}\r\n",
+                "x".repeat(1500)
+            );
+            stream.write_all(start.as_bytes()).unwrap();
+            let mut rest = format!(
+                "Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept
+            )
+            .into_bytes();
+            rest.extend(Vec::from(
+                DataFrame::build(b"welcome", FrameType::Text, Agent::Server).unwrap(),
+            ));
+            stream.write_all(&rest).unwrap();
+            let _ = stream.read(&mut buf);
+        });
+
+        let (tx, rx) = channel();
+        let mut client = WSClientBuilder::new(&url)
+            .connect(Recorder {
+                messages: Mutex::new(tx),
+            })
+            .unwrap();
+        client.ws_stream.read().unwrap();
+        assert_eq!(rx.try_recv().unwrap(), "welcome");
+    }
+
+    /// Answers `count` upgrades with a 302 to `location`, `{self}` stands for its own address
+    fn redirecting_server(location: &str, count: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let location = location.replace("{self}", &addr);
+
+        thread::spawn(move || {
+            for _ in 0..count {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = stream.read(&mut [0u8; 2048]);
+                let res = format!(
+                    "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                    location
+                );
+                stream.write_all(res.as_bytes()).unwrap();
+            }
+        });
+
+        format!("ws://{}/", addr)
+    }
+
+    #[test]
+    fn test_follow_redirect() {
+        let (regional, rx) = fake_server("");
+        let url = redirecting_server(&regional, 1);
+
+        let client = WSClientBuilder::new(&url)
+            .follow_redirects(3, RedirectPolicy::SameScheme)
+            .connect(TestHandler);
+        assert!(client.is_ok());
+
+        let host = Url::parse(&regional).unwrap();
+        let headers = rx.recv().unwrap();
+        assert_eq!(
+            &headers["host"],
+            format!("127.0.0.1:{}", host.port().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_redirects_not_followed_by_default() {
+        let url = redirecting_server("/elsewhere", 1);
+
+        match WSClientBuilder::new(&url).connect(TestHandler) {
+            Err(ClientError::Handshake(e)) => {
+                assert_eq!(e.status, 302);
+                assert_eq!(e.headers.get("location"), Some("/elsewhere"));
+            }
+            _ => panic!("expected the redirect to be returned"),
+        }
+    }
+
+    #[test]
+    fn test_redirect_limit_and_policy() {
+        let url = redirecting_server("ws://{self}/again", 3);
+        let res = WSClientBuilder::new(&url)
+            .follow_redirects(2, RedirectPolicy::SameScheme)
+            .connect(TestHandler);
+        match res {
+            Err(ClientError::Connection(e)) => assert_eq!(e, "More than 2 redirects"),
+            _ => panic!("expected the redirect limit to be hit"),
+        }
+
+        let url = redirecting_server("wss://{self}/secure", 1);
+        let res = WSClientBuilder::new(&url)
+            .follow_redirects(2, RedirectPolicy::SameScheme)
+            .connect(TestHandler);
+        match res {
+            Err(ClientError::Connection(e)) => assert!(e.contains("not allowed")),
+            _ => panic!("expected the scheme change to be refused"),
+        }
+    }
+    #[test]
+    fn test_credentials_not_sent_to_another_host() {
+        let (regional, rx) = fake_server("");
+        let url = redirecting_server(&regional, 1);
+
+        let client = WSClientBuilder::new(&url)
+            .header("Authorization", "Bearer secret")
+            .header("cookie", "session=1")
+            .header("X-Region", "eu")
+            .follow_redirects(1, RedirectPolicy::SameScheme)
+            .connect(TestHandler);
+        assert!(client.is_ok());
+
+        // same host, other port
+        let headers = rx.recv().unwrap();
+        assert_eq!(headers.get("authorization"), None);
+        assert_eq!(headers.get("cookie"), None);
+        assert_eq!(headers.get("x-region"), Some("eu"));
+    }
+
+    #[test]
+    fn test_no_downgrade_from_wss() {
+        let from = Url::parse("wss://api.example.com/feed").unwrap();
+        for policy in [RedirectPolicy::SameScheme, RedirectPolicy::AllowUpgrade] {
+            assert!(redirect_target(&from, "ws://api.example.com/feed", policy).is_err());
+            assert!(redirect_target(&from, "http://api.example.com/feed", policy).is_err());
+        }
+        assert!(redirect_target(&from, "/other", RedirectPolicy::AllowUpgrade).is_ok());
+    }
+}
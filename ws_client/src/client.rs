@@ -1,13 +1,34 @@
 use rand::RngCore;
-use std::collections::HashMap;
-use std::io::{BufRead, Read, Write};
-use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
-use std::str::FromStr;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+#[cfg(feature = "rustls")]
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use url::Url;
 
-use ws_core::http_utils::{parse_headers, validate_http_version};
-use ws_core::{base64, ConnectionStatus, WSHandler, WSStream};
+use ws_core::http_utils::{
+    parse_response, read_head, HeadError, HeadLimits, Headers, ResponseHead,
+};
+use ws_core::keepalive::KeepAlive;
+#[cfg(feature = "rustls")]
+use ws_core::rustls::ClientConfig;
+use ws_core::send_queue::{SendQueueConfig, SendQueueStats};
+use ws_core::socket::SocketOptions;
+use ws_core::{base64, sha1, ConnectionStatus, WSHandler, WSStream};
+
+use crate::builder::{RequestOptions, WSClientBuilder};
+use crate::errors::{get_handshake_error, ClientError, HandshakeError};
+use crate::proxy::{tunnel, Proxy};
+
+/// Bytes of a rejected upgrade's body kept in `HandshakeError::body`
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// What the server agreed to in its 101
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Negotiated {
+    pub(crate) protocol: Option<String>,
+    pub(crate) extensions: Vec<String>,
+}
 
 pub struct WSClient<H> {
     pub ws_state: ConnectionStatus,
@@ -18,148 +39,351 @@ impl<H> WSClient<H>
 where
     H: WSHandler,
 {
-    pub fn connect(host: &str, handler: H) -> Result<Self, String> {
-        let host_uri = match Url::parse(host) {
-            Ok(uri) => uri,
-            Err(_) => return Err("Invalid host url".to_string()),
-        };
-
-        let soc_addr = SocketAddrV4::new(
-            Ipv4Addr::from_str(host_uri.host_str().unwrap()).unwrap(),
-            host_uri.port().unwrap(),
-        );
-
-        let mut tcp_stream = match TcpStream::connect(soc_addr) {
-            Ok(t) => t,
-            Err(e) => {
-                println!("{:?}", e);
-                return Err("Connection failed".to_string());
-            }
-        };
-
-        match handshake(&host_uri, &mut tcp_stream) {
-            Ok(_) => Ok(WSClient {
-                ws_state: ConnectionStatus::Open,
-                ws_stream: WSStream {
-                    stream: tcp_stream,
-                    handler: Arc::new(handler),
-                },
-            }),
-            Err(e) => Err(e),
-        }
+    /// Connects over TLS when the scheme is wss://, trusting the Mozilla root store. See
+    /// `WSClientBuilder` for headers, subprotocols and timeouts.
+    pub fn connect(host: &str, handler: H) -> Result<Self, ClientError> {
+        WSClientBuilder::new(host).connect(handler)
+    }
+
+    /// Connects to a wss:// URL verifying the server with `config`, see `tls::client_config`
+    #[cfg(feature = "rustls")]
+    pub fn connect_tls(
+        host: &str,
+        handler: H,
+        config: Arc<ClientConfig>,
+    ) -> Result<Self, ClientError> {
+        WSClientBuilder::new(host)
+            .tls_config(config)
+            .connect(handler)
+    }
+
+    /// Pings the server every `keepalive.interval` while `ws_stream` is being read
+    pub fn set_keepalive(&mut self, keepalive: KeepAlive) -> Result<(), String> {
+        self.ws_stream.set_keepalive(Some(keepalive))
+    }
+
+    /// Buffers writes in a bounded queue, see `WSStream::set_send_queue`
+    pub fn set_send_queue(&mut self, config: SendQueueConfig) -> Result<(), String> {
+        self.ws_stream.set_send_queue(config)
+    }
+
+    pub fn send_queue_stats(&self) -> Option<SendQueueStats> {
+        self.ws_stream.send_queue_stats()
+    }
+
+    /// Round-trip time to the server, see `WSStream::ping`
+    pub fn ping(&mut self, timeout: Duration) -> Result<Duration, String> {
+        self.ws_stream.ping(timeout)
+    }
+
+    /// Smoothed round-trip time to the server
+    pub fn rtt(&self) -> Option<Duration> {
+        self.ws_stream.rtt()
+    }
+
+    /// Subprotocol the server chose among the ones offered with `WSClientBuilder::protocol`
+    pub fn protocol(&self) -> Option<&str> {
+        self.ws_stream.info().protocol.as_deref()
+    }
+
+    /// Extensions the server accepted, with their parameters
+    pub fn extensions(&self) -> &[String] {
+        &self.ws_stream.info().extensions
     }
 }
-fn handshake(host: &Url, stream: &mut TcpStream) -> Result<(), String> {
-    let handshake = create_handshake(host);
 
-    match stream.write_all(handshake.as_bytes()) {
-        Ok(_) => println!("\nClient Handshake\n{handshake}\n"),
+/// Resolves the URL host and connects to the first address accepting the connection, on port
+/// 80 for ws:// and 443 for wss:// unless the URL has one
+pub(crate) fn dial(
+    host: &Url,
+    timeout: Option<Duration>,
+    options: &SocketOptions,
+) -> Result<TcpStream, String> {
+    check_scheme(host)?;
+
+    let addrs = match host.socket_addrs(|| None) {
+        Ok(a) => a,
         Err(e) => {
-            println!("Failed to write handshake: {:?}", e);
-            return Err("Handshake failed".to_string());
+            println!("{:?}", e);
+            return Err("Failed to resolve host".to_string());
         }
     };
 
-    let mut buf = [0u8; 512];
-    let r_size = match stream.read(&mut buf) {
-        Ok(n) => n,
-        Err(e) => {
-            println!("Failed to read handshake: {:?}", e);
-            return Err("Handshake failed".to_string());
+    connect_any(addrs, timeout, options)
+}
+
+/// Same as `dial` through a tunnel opened by `proxy`
+pub(crate) fn dial_proxy(
+    host: &Url,
+    proxy: &Proxy,
+    timeout: Option<Duration>,
+    options: &SocketOptions,
+) -> Result<TcpStream, String> {
+    check_scheme(host)?;
+
+    let port = host.port_or_known_default().unwrap_or(80);
+    tunnel(
+        proxy,
+        host.host_str().unwrap_or_default(),
+        port,
+        timeout,
+        options,
+    )
+}
+
+fn check_scheme(host: &Url) -> Result<(), String> {
+    match host.scheme() {
+        "ws" | "wss" => (),
+        s => return Err(format!("Unsupported scheme {}", s)),
+    }
+
+    match host.host_str() {
+        Some(_) => Ok(()),
+        None => Err("Invalid host url".to_string()),
+    }
+}
+
+pub(crate) fn connect_any(
+    addrs: Vec<SocketAddr>,
+    timeout: Option<Duration>,
+    options: &SocketOptions,
+) -> Result<TcpStream, String> {
+    let mut last_err = None;
+    for addr in addrs {
+        match options.connect(addr, timeout) {
+            Ok(t) => return Ok(t),
+            Err(e) => last_err = Some(e),
         }
-    };
+    }
 
-    parse_handshake(buf[..r_size].to_vec())?;
+    match last_err {
+        Some(e) => println!("{:?}", e),
+        None => println!("No address to connect to"),
+    }
+    Err("Connection failed".to_string())
+}
 
-    Ok(())
+/// Sends the upgrade request and reads the response head, within `limits` and before
+/// `deadline`. Returns what the server agreed to and the bytes it sent after the head.
+/// `socket` is the connection under `stream`.
+pub(crate) fn handshake<S: Read + Write>(
+    host: &Url,
+    stream: &mut S,
+    socket: &TcpStream,
+    options: &RequestOptions,
+    limits: &HeadLimits,
+    deadline: Option<Instant>,
+) -> Result<(Negotiated, Vec<u8>), ClientError> {
+    let (handshake, key) = create_handshake(host, options);
+
+    // not logged, it may carry credentials
+    if let Err(e) = stream.write_all(handshake.as_bytes()) {
+        println!("Failed to write handshake: {:?}", e);
+        return Err(ClientError::Connection("Handshake failed".to_string()));
+    }
+
+    let (head, rest) = match read_head(stream, socket, limits, deadline, parse_response) {
+        Ok(h) => h,
+        Err(e @ (HeadError::TimedOut | HeadError::Closed | HeadError::Io(_))) => {
+            println!("Failed to read handshake: {}", e);
+            return Err(ClientError::Connection(e.to_string()));
+        }
+        Err(e) => return Err(get_handshake_error(0, &e.to_string()).into()),
+    };
+
+    match parse_handshake(head, &rest, &key, &options.protocols, &options.extensions) {
+        Ok(negotiated) => Ok((negotiated, rest)),
+        Err(mut e) => {
+            read_body(stream, &mut e);
+            Err(ClientError::Handshake(e))
+        }
+    }
 }
 
-fn create_handshake(host: &Url) -> String {
+/// Returns the request and the `Sec-WebSocket-Key` it carries
+pub(crate) fn create_handshake(host: &Url, options: &RequestOptions) -> (String, String) {
     let mut handshake: String = String::from("");
     handshake.push_str("GET ");
-    handshake.push_str(host.as_str());
-    handshake.push_str(" HTTP/1.1\n");
+    handshake.push_str(&request_target(host));
+    handshake.push_str(" HTTP/1.1\r\n");
     handshake.push_str("Host: ");
-    handshake.push_str(host.host_str().unwrap());
-    handshake.push('\n');
-    handshake.push_str("Upgrade: websocket\nConnection: Upgrade\nSec-WebSocket-Version: 13\n");
+    handshake.push_str(&host_header(host));
+    handshake.push_str("\r\n");
+    handshake
+        .push_str("Upgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Version: 13\r\n");
     handshake.push_str("Sec-WebSocket-Key: ");
     let key = sec_ws_key();
     handshake.push_str(&key);
-    handshake
+    handshake.push_str("\r\n");
+    if let Some(origin) = &options.origin {
+        handshake.push_str(&format!("Origin: {}\r\n", origin));
+    }
+    if !options.protocols.is_empty() {
+        handshake.push_str(&format!(
+            "Sec-WebSocket-Protocol: {}\r\n",
+            options.protocols.join(", ")
+        ));
+    }
+    if !options.extensions.is_empty() {
+        handshake.push_str(&format!(
+            "Sec-WebSocket-Extensions: {}\r\n",
+            options.extensions.join(", ")
+        ));
+    }
+    for (name, value) in &options.headers {
+        handshake.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    handshake.push_str("\r\n");
+    (handshake, key)
 }
 
-fn parse_handshake(c_handshake: Vec<u8>) -> Result<(), String> {
-    let h_lines: Vec<String> = c_handshake
-        .lines()
-        .map(|res| res.unwrap())
-        .take_while(|l| !l.is_empty())
-        .collect();
-
-    if h_lines.is_empty() {
-        return Err("Invalid handshake".to_string());
+/// Origin-form request-target, the path and query of the URL
+fn request_target(host: &Url) -> String {
+    let mut target = match host.path() {
+        "" => "/".to_string(),
+        p => p.to_string(),
+    };
+    if let Some(q) = host.query() {
+        target.push('?');
+        target.push_str(q);
     }
+    target
+}
 
-    let status: Vec<&str> = h_lines.first().unwrap().splitn(3, " ").collect();
-    if status.len() != 3 {
-        return Err("Invalid status line".to_string());
+/// Host with the port when it is not the default one of the scheme, IPv6 literals keep their
+/// brackets
+fn host_header(host: &Url) -> String {
+    let name = host.host_str().unwrap_or_default();
+    match host.port() {
+        Some(port) => format!("{}:{}", name, port),
+        None => name.to_string(),
     }
+}
 
-    let _ = match validate_http_version(status[0]) {
-        Err(e) => Err(e),
-        _ => Ok(()),
-    };
+/// Checks the server response against RFC 6455 4.1, `body` is what followed the head and
+/// `protocols` and `extensions` are the ones the request offered. Returns the subprotocol and
+/// extensions the server chose.
+pub(crate) fn parse_handshake(
+    head: ResponseHead,
+    body: &[u8],
+    key: &str,
+    protocols: &[String],
+    extensions: &[String],
+) -> Result<Negotiated, HandshakeError> {
+    let mut error = get_handshake_error(head.status, "");
+    error.body = String::from_utf8_lossy(body).to_string();
 
-    let _ = match verify_http_status(status[1]) {
-        Err(e) => Err(e),
-        _ => Ok(()),
-    };
+    let result = verify_http_status(head.status, &head.reason)
+        .and_then(|_| validate_headers(&head.headers, key, protocols, extensions));
+    error.headers = head.headers;
+
+    match result {
+        Ok(_) => Ok(Negotiated {
+            protocol: error
+                .headers
+                .get("sec-websocket-protocol")
+                .map(|p| p.to_string()),
+            extensions: error.headers.tokens("sec-websocket-extensions"),
+        }),
+        Err(msg) => {
+            error.message = msg;
+            Err(error)
+        }
+    }
+}
 
-    let headers: HashMap<String, String> = parse_headers(&h_lines);
+/// Reads what is left of the body of a rejected upgrade, up to its `Content-Length`
+fn read_body<S: Read>(stream: &mut S, error: &mut HandshakeError) {
+    let length = match error.headers.get("content-length") {
+        Some(l) => l.parse::<usize>().unwrap_or(0).min(MAX_ERROR_BODY),
+        None => return,
+    };
 
-    if let Err(e) = validate_headers(&headers) {
-        return Err(e.to_string());
+    if error.body.len() >= length {
+        return;
     }
 
-    Ok(())
+    let mut rest = vec![0u8; length - error.body.len()];
+    let mut filled = 0;
+    while filled < rest.len() {
+        match stream.read(&mut rest[filled..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => filled += n,
+        }
+    }
+    error
+        .body
+        .push_str(&String::from_utf8_lossy(&rest[..filled]));
 }
 
 fn sec_ws_key() -> String {
     let mut nonce = [0u8; 16];
-    rand::thread_rng().fill_bytes(&mut nonce);
+    rand::rng().fill_bytes(&mut nonce);
     base64::encode(&nonce)
 }
 
-fn validate_headers(headers: &HashMap<String, String>) -> Result<(), &str> {
-    match headers.get("upgrade") {
-        Some(upgrade) => {
-            if !upgrade.eq_ignore_ascii_case("websocket") {
-                return Err("Invalid upgrade header");
+fn accept_key(key: &str) -> String {
+    let hash_str = sha1::hash(&(key.to_string() + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11"));
+    base64::encode(hash_str.as_slice())
+}
+
+fn validate_headers(
+    headers: &Headers,
+    key: &str,
+    protocols: &[String],
+    extensions: &[String],
+) -> Result<(), String> {
+    if !headers.has_token("upgrade", "websocket") {
+        return Err("Invalid upgrade header".to_string());
+    }
+
+    if !headers.has_token("connection", "upgrade") {
+        return Err("Invalid connection header".to_string());
+    }
+
+    let accepts: Vec<&str> = headers.get_all("sec-websocket-accept").collect();
+    match accepts[..] {
+        [accept] => {
+            if accept != accept_key(key) {
+                return Err("Sec-WebSocket-Accept does not match the key sent".to_string());
             }
         }
-        None => return Err("Invalid upgrade header"),
-    };
+        _ => return Err("Invalid websocket key".to_string()),
+    }
 
-    match headers.get("connection") {
-        Some(connection) => {
-            if !connection.eq_ignore_ascii_case("upgrade") {
-                return Err("Invalid connection header");
-            }
+    for ext in headers.tokens("sec-websocket-extensions") {
+        let name = ext.split(';').next().unwrap_or_default().trim();
+        if !extensions.iter().any(|e| {
+            e.split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case(name)
+        }) {
+            return Err(format!("Extension {} was not requested", name));
         }
-        None => return Err("Invalid connection header"),
     }
 
-    match headers.get("sec-websocket-accept") {
-        Some(_) => (),
-        None => return Err("Invalid websocket key"),
+    let chosen: Vec<&str> = headers.get_all("sec-websocket-protocol").collect();
+    match chosen[..] {
+        [] => (),
+        [protocol] if protocols.iter().any(|p| p == protocol) => (),
+        _ => {
+            return Err(format!(
+                "Subprotocol {} was not requested",
+                chosen.join(", ")
+            ))
+        }
     }
 
     Ok(())
 }
 
-fn verify_http_status(status: &str) -> Result<(), &str> {
-    match status {
-        "101" => Ok(()),
-        _ => Err("Invalid ws_server status"),
+fn verify_http_status(code: u16, reason: &str) -> Result<(), String> {
+    match code {
+        101 => Ok(()),
+        _ => Err(format!("Unexpected status {} {}", code, reason)
+            .trim_end()
+            .to_string()),
     }
 }
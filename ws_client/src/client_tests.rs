@@ -1,61 +1,185 @@
-// #[cfg(test)]
-// mod tests {
-//     use ws_core::http_utils::parse_headers;
-//     use url::Url;
-//
-//     #[test]
-//     fn test_valid_handshake() {
-//         let ws_client = WSClient::new(Url::parse("wss://www.example.com/chat/").unwrap()).unwrap();
-//
-//         let handshake = ws_client.create_handshake();
-//
-//         let h_lines: Vec<String> = handshake
-//             .lines()
-//             .take_while(|l| !l.is_empty())
-//             .map(|z| z.to_string())
-//             .collect();
-//
-//         assert_eq!(
-//             h_lines.first(),
-//             Some(&String::from("GET wss://www.example.com/chat/ HTTP/1.1"))
-//         );
-//
-//         let headers = parse_headers(&h_lines);
-//
-//         assert_eq!(headers.get("host"), Some(&String::from("www.example.com")));
-//         assert_eq!(headers.get("connection"), Some(&String::from("Upgrade")));
-//         assert_eq!(headers.get("upgrade"), Some(&String::from("websocket")));
-//         assert!(headers.contains_key("sec-websocket-key"));
-//         assert_eq!(
-//             headers.get("sec-websocket-version"),
-//             Some(&String::from("13"))
-//         );
-//     }
-//
-//     #[test]
-//     fn test_server_handshake_parsing() {
-//         let server_handshake = "HTTP/1.1 101 Switching Protocols\n\
-//         upgrade: websocket\n\
-//         Connection: Upgrade\n\
-//         Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
-//
-//         let ws_client = WSClient::new(Url::parse("wss://www.example.com/chat/").unwrap()).unwrap();
-//
-//         ws_client
-//             .parse_handshake(server_handshake.as_bytes().to_vec())
-//             .unwrap();
-//     }
-//
-//     #[test]
-//     fn test_server_handshake_parsing_invalid() {
-//         let server_handshake = "HTTP/1.1 101 Switching Protocols\n\
-//         Connection: Upgrade\n\
-//         Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
-//
-//         let ws_client = WSClient::new(Url::parse("wss://www.example.com/chat/").unwrap()).unwrap();
-//
-//         assert!(ws_client
-//             .parse_handshake(server_handshake.as_bytes().to_vec())
-//             .is_err());
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use crate::builder::RequestOptions;
+    use crate::client::{create_handshake, dial, parse_handshake, Negotiated, WSClient};
+    use crate::errors::{get_handshake_error, ClientError, HandshakeError};
+    use crate::test_common::TestHandler;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use url::Url;
+    use ws_core::http_utils::{parse_request, parse_response, HeadLimits, Headers};
+    use ws_core::socket::SocketOptions;
+
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+    fn parse(
+        response: &[u8],
+        key: &str,
+        protocols: &[String],
+        extensions: &[String],
+    ) -> Result<Negotiated, HandshakeError> {
+        match parse_response(response, &HeadLimits::default()) {
+            Ok((head, len)) => parse_handshake(head, &response[len..], key, protocols, extensions),
+            Err(e) => Err(get_handshake_error(0, &e.to_string())),
+        }
+    }
+
+    fn request(url: &str) -> (String, Headers) {
+        let (handshake, _) =
+            create_handshake(&Url::parse(url).unwrap(), &RequestOptions::default());
+
+        let (head, len) = parse_request(handshake.as_bytes(), &HeadLimits::default()).unwrap();
+        assert_eq!(len, handshake.len());
+
+        (
+            format!("{} {} {}", head.method, head.target, head.version),
+            head.headers,
+        )
+    }
+
+    #[test]
+    fn test_valid_handshake() {
+        let (request_line, headers) = request("wss://www.example.com/chat/");
+
+        assert_eq!(request_line, "GET /chat/ HTTP/1.1");
+        assert_eq!(headers.get("host"), Some("www.example.com"));
+        assert_eq!(headers.get("connection"), Some("Upgrade"));
+        assert_eq!(headers.get("upgrade"), Some("websocket"));
+        assert!(headers.contains("sec-websocket-key"));
+        assert_eq!(headers.get("sec-websocket-version"), Some("13"));
+    }
+
+    #[test]
+    fn test_request_target_and_host() {
+        let (request_line, headers) = request("ws://feeds.internal:9000/stream?topic=a%20b&x=1");
+        assert_eq!(request_line, "GET /stream?topic=a%20b&x=1 HTTP/1.1");
+        assert_eq!(headers.get("host"), Some("feeds.internal:9000"));
+
+        // default ports are left out
+        let (request_line, headers) = request("ws://feeds.internal:80");
+        assert_eq!(request_line, "GET / HTTP/1.1");
+        assert_eq!(headers.get("host"), Some("feeds.internal"));
+
+        let (_, headers) = request("wss://[::1]:8443/");
+        assert_eq!(headers.get("host"), Some("[::1]:8443"));
+    }
+
+    #[test]
+    fn test_dial_hostname() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+
+        let url = Url::parse(&format!("ws://localhost:{}/", port)).unwrap();
+        assert!(dial(&url, None, &SocketOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_dial_unsupported_scheme() {
+        let url = Url::parse("http://127.0.0.1:1/").unwrap();
+        assert!(dial(&url, None, &SocketOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_server_handshake_parsing() {
+        let server_handshake = "HTTP/1.1 101 Switching Protocols\r\n\
+        upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+
+        parse(server_handshake.as_bytes(), KEY, &[], &[]).unwrap();
+    }
+
+    #[test]
+    fn test_server_handshake_parsing_invalid() {
+        let server_handshake = "HTTP/1.1 101 Switching Protocols\n\
+        Connection: Upgrade\n\
+        Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+        assert!(parse(server_handshake.as_bytes(), KEY, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_accept_must_match_key() {
+        let server_handshake = "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: AAAAAAAAAAAAAAAAAAAAAAAAAAA=\r\n\r\n";
+
+        let e = parse(server_handshake.as_bytes(), KEY, &[], &[]).unwrap_err();
+        assert_eq!(e.status, 101);
+        assert!(e.message.contains("Sec-WebSocket-Accept"));
+    }
+
+    #[test]
+    fn test_status_must_be_101() {
+        let server_handshake = "HTTP/1.1 404 Not Found\r\n\
+        Content-Length: 9\r\n\r\n\
+        not found";
+
+        let e = parse(server_handshake.as_bytes(), KEY, &[], &[]).unwrap_err();
+        assert_eq!(e.status, 404);
+        assert_eq!(e.message, "Unexpected status 404 Not Found");
+        assert_eq!(e.body, "not found");
+    }
+
+    #[test]
+    fn test_unrequested_protocol_and_extension() {
+        let response = |extra: &str| {
+            format!(
+                "HTTP/1.1 101 Switching Protocols\r\n\
+                Upgrade: websocket\r\n\
+                Connection: keep-alive, Upgrade\r\n\
+                Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+                {}\r\n\r\n",
+                extra
+            )
+        };
+
+        let protocols = vec!["chat".to_string()];
+        let extensions = vec!["permessage-deflate; client_max_window_bits".to_string()];
+
+        let with_protocol = response("Sec-WebSocket-Protocol: chat");
+        assert!(parse(with_protocol.as_bytes(), KEY, &[], &[]).is_err());
+        let chosen = parse(with_protocol.as_bytes(), KEY, &protocols, &[]).unwrap();
+        assert_eq!(chosen.protocol, Some("chat".to_string()));
+
+        let with_extension = response("Sec-WebSocket-Extensions: permessage-deflate");
+        assert!(parse(with_extension.as_bytes(), KEY, &[], &[]).is_err());
+        let chosen = parse(with_extension.as_bytes(), KEY, &[], &extensions).unwrap();
+        assert_eq!(chosen.extensions, ["permessage-deflate"]);
+    }
+
+    #[test]
+    fn test_rejected_upgrade_carries_status_and_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let body = "x".repeat(2000);
+        let sent = body.clone();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 1024]);
+            let res = format!(
+                "HTTP/1.1 403 Forbidden\r\nContent-Length: {}\r\n\r\n{}",
+                sent.len(),
+                sent
+            );
+            stream.write_all(res.as_bytes()).unwrap();
+        });
+
+        let url = format!("ws://127.0.0.1:{}/", port);
+        match WSClient::connect(&url, TestHandler) {
+            Err(ClientError::Handshake(e)) => {
+                assert_eq!(e.status, 403);
+                assert_eq!(e.body, body);
+            }
+            _ => panic!("expected a handshake error"),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::fmt::Formatter;
+use ws_core::http_utils::Headers;
+
+/// The server response did not complete the upgrade
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HandshakeError {
+    /// Status code of the response, 0 when there was no valid status line
+    pub status: u16,
+    pub message: String,
+    pub headers: Headers,
+    pub body: String,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "status: {} message: {}", self.status, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ClientError {
+    /// Resolving, connecting, TLS or reading the response failed
+    Connection(String),
+    Handshake(HandshakeError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connection(e) => write!(f, "{}", e),
+            ClientError::Handshake(e) => write!(f, "Handshake rejected, {}", e),
+        }
+    }
+}
+
+impl From<String> for ClientError {
+    fn from(e: String) -> Self {
+        ClientError::Connection(e)
+    }
+}
+
+impl From<HandshakeError> for ClientError {
+    fn from(e: HandshakeError) -> Self {
+        ClientError::Handshake(e)
+    }
+}
+
+pub fn get_handshake_error(status: u16, msg: &str) -> HandshakeError {
+    HandshakeError {
+        status,
+        message: msg.to_string(),
+        headers: Headers::new(),
+        body: String::new(),
+    }
+}
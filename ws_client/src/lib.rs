@@ -1,2 +1,13 @@
+pub mod builder;
+mod builder_tests;
 pub mod client;
 mod client_tests;
+pub mod errors;
+pub mod proxy;
+mod proxy_tests;
+pub mod reconnect;
+mod reconnect_tests;
+#[cfg(test)]
+mod test_common;
+#[cfg(feature = "rustls")]
+pub mod tls;
@@ -0,0 +1,303 @@
+use rand::Rng;
+use std::collections::VecDeque;
+use std::net::Shutdown;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::builder::WSClientBuilder;
+use crate::errors::ClientError;
+use ws_core::data_frame_tx::FrameType;
+use ws_core::{WSHandler, WSStream};
+
+/// Connection events of a `ReconnectingClient` on top of the messages
+pub trait ReconnectHandler: WSHandler {
+    /// The connection dropped, messages written from now on are buffered
+    fn on_disconnected(&self, _reason: &str) {}
+    /// Connected again after `attempts` attempts, the buffered messages are already sent
+    fn on_reconnected(&self, _attempts: u32) {}
+}
+
+/// Waits `initial_delay * multiplier^attempt` capped at `max_delay` before each attempt, minus
+/// up to `jitter` of it at random so clients dropped together do not come back together.
+/// Gives up after `max_attempts` attempts in a row when set.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReconnectConfig {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Between 0 and 1
+    pub jitter: f64,
+    pub max_attempts: Option<u32>,
+    /// Text and binary messages kept while disconnected
+    pub max_buffered: usize,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+            max_buffered: 1024,
+        }
+    }
+}
+
+/// Delay before the attempt number `attempt`, counted from 0
+pub(crate) fn backoff(config: &ReconnectConfig, attempt: u32) -> Duration {
+    let delay = config
+        .initial_delay
+        .mul_f64(config.multiplier.max(1.0).powi(attempt.min(64) as i32))
+        .min(config.max_delay);
+
+    let jitter = config.jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+        return delay;
+    }
+    delay.mul_f64(1.0 - rand::rng().random_range(0.0..=jitter))
+}
+
+struct State<H> {
+    stream: Option<WSStream<H>>,
+    /// Bumped on every new connection so a stale reader cannot drop a newer one
+    generation: u64,
+    buffer: VecDeque<(FrameType, Vec<u8>)>,
+    closed: bool,
+}
+
+struct Inner<H> {
+    builder: WSClientBuilder,
+    config: ReconnectConfig,
+    handler: Arc<H>,
+    state: Mutex<State<H>>,
+    closing: Condvar,
+}
+
+/// A client connecting again with the same builder whenever the connection fails or the
+/// server closes it with anything but 1000. Clones share the connection, `read` has to be
+/// called in a loop on one of them since it is what reconnects.
+pub struct ReconnectingClient<H> {
+    inner: Arc<Inner<H>>,
+}
+
+impl<H> Clone for ReconnectingClient<H> {
+    fn clone(&self) -> Self {
+        ReconnectingClient {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<H> ReconnectingClient<H>
+where
+    H: ReconnectHandler,
+{
+    /// Fails when the first connection cannot be made, later ones are retried
+    pub fn connect(
+        builder: WSClientBuilder,
+        config: ReconnectConfig,
+        handler: H,
+    ) -> Result<Self, ClientError> {
+        let handler = Arc::new(handler);
+        let client = builder.connect_with(handler.clone())?;
+
+        Ok(ReconnectingClient {
+            inner: Arc::new(Inner {
+                builder,
+                config,
+                handler,
+                state: Mutex::new(State {
+                    stream: Some(client.ws_stream),
+                    generation: 0,
+                    buffer: VecDeque::new(),
+                    closed: false,
+                }),
+                closing: Condvar::new(),
+            }),
+        })
+    }
+
+    /// Reads the next frame, reconnecting first when the connection is down. Only fails once
+    /// the client is closed, the server closed normally or reconnecting gave up.
+    pub fn read(&self) -> Result<(), String> {
+        let (mut stream, generation) = loop {
+            let state = self.inner.state.lock().unwrap();
+            if state.closed {
+                return Err("Client closed".to_string());
+            }
+            match &state.stream {
+                Some(s) => break (s.clone(), state.generation),
+                None => {
+                    drop(state);
+                    self.reconnect()?;
+                }
+            }
+        };
+
+        let e = match stream.read() {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if stream.peer_close_code() == Some(1000) {
+            self.inner.state.lock().unwrap().closed = true;
+            self.disconnect(generation, &e);
+            return Err(format!("Closed by server: {}", e));
+        }
+
+        self.disconnect(generation, &e);
+        self.reconnect()
+    }
+
+    /// Writes to the current connection, or buffers text and binary messages while there is
+    /// none. The state is not locked while writing, so a slow connection does not hold up
+    /// `read`, `close` or the other clones.
+    pub fn write(&self, data: &[u8], f_type: FrameType) -> Result<(), String> {
+        loop {
+            let (mut stream, generation) = {
+                let mut state = self.inner.state.lock().unwrap();
+                if state.closed {
+                    return Err("Client closed".to_string());
+                }
+                match &state.stream {
+                    Some(s) => (s.clone(), state.generation),
+                    None => return self.buffer(&mut state, data, f_type),
+                }
+            };
+
+            // a failed connection is dropped and the message goes to the next one, or the buffer
+            match stream.write(data, f_type) {
+                Ok(_) => return Ok(()),
+                Err(e) => self.disconnect(generation, &e),
+            }
+        }
+    }
+
+    /// Closes the connection for good, a `read` waiting to reconnect returns
+    pub fn close(&self, code: u16, reason: &str) -> Result<(), String> {
+        let mut state = self.inner.state.lock().unwrap();
+        state.closed = true;
+        state.buffer.clear();
+        self.inner.closing.notify_all();
+
+        match state.stream.take() {
+            Some(mut stream) => stream.close(code, reason),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.inner.state.lock().unwrap().stream.is_some()
+    }
+
+    /// Messages waiting for the next connection
+    pub fn buffered(&self) -> usize {
+        self.inner.state.lock().unwrap().buffer.len()
+    }
+
+    /// Keeps a text or binary message for the next connection
+    fn buffer(&self, state: &mut State<H>, data: &[u8], f_type: FrameType) -> Result<(), String> {
+        match f_type {
+            FrameType::Text | FrameType::Binary => {
+                if state.buffer.len() >= self.inner.config.max_buffered {
+                    Err("Reconnect buffer full".to_string())
+                } else {
+                    state.buffer.push_back((f_type, data.to_vec()));
+                    Ok(())
+                }
+            }
+            _ => Err("Not connected".to_string()),
+        }
+    }
+
+    fn disconnect(&self, generation: u64, reason: &str) {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+
+        if let Some(stream) = state.stream.take() {
+            let _ = stream.stream.shutdown(Shutdown::Both);
+            drop(state);
+            self.inner.handler.on_disconnected(reason);
+        }
+    }
+
+    fn reconnect(&self) -> Result<(), String> {
+        let config = &self.inner.config;
+        let mut attempt = 0;
+
+        loop {
+            if config.max_attempts.is_some_and(|max| attempt >= max) {
+                self.inner.state.lock().unwrap().closed = true;
+                return Err(format!("Gave up reconnecting after {} attempts", attempt));
+            }
+
+            let deadline = Instant::now() + backoff(config, attempt);
+            let mut state = self.inner.state.lock().unwrap();
+            loop {
+                if state.closed {
+                    return Err("Client closed".to_string());
+                }
+                if state.stream.is_some() {
+                    return Ok(());
+                }
+
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                state = self
+                    .inner
+                    .closing
+                    .wait_timeout(state, deadline - now)
+                    .unwrap()
+                    .0;
+            }
+            drop(state);
+
+            attempt += 1;
+            let mut stream = match self.inner.builder.connect_with(self.inner.handler.clone()) {
+                Ok(c) => c.ws_stream,
+                Err(e) => {
+                    println!("Reconnect attempt {} failed: {}", attempt, e);
+                    continue;
+                }
+            };
+
+            let mut state = self.inner.state.lock().unwrap();
+            if state.closed {
+                let _ = stream.close(1000, "Client closed");
+                return Err("Client closed".to_string());
+            }
+
+            if let Err(e) = flush(&mut stream, &mut state.buffer) {
+                println!("Reconnect attempt {} failed: {}", attempt, e);
+                continue;
+            }
+
+            state.stream = Some(stream);
+            state.generation += 1;
+            drop(state);
+
+            self.inner.handler.on_reconnected(attempt);
+            return Ok(());
+        }
+    }
+}
+
+/// Sends the buffered messages in order, the ones not sent stay buffered
+fn flush<H: WSHandler>(
+    stream: &mut WSStream<H>,
+    buffer: &mut VecDeque<(FrameType, Vec<u8>)>,
+) -> Result<(), String> {
+    while let Some((f_type, data)) = buffer.pop_front() {
+        if let Err(e) = stream.write(&data, f_type) {
+            buffer.push_front((f_type, data));
+            return Err(e);
+        }
+    }
+    Ok(())
+}
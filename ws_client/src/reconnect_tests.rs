@@ -0,0 +1,237 @@
+#[cfg(test)]
+mod tests {
+    use crate::builder::WSClientBuilder;
+    use crate::reconnect::{backoff, ReconnectConfig, ReconnectHandler, ReconnectingClient};
+    use crate::test_common;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+    use ws_core::data_frame_rx::DFParser;
+    use ws_core::data_frame_tx::{Agent, DataFrame, FrameType};
+    use ws_core::WSHandler;
+
+    struct TestHandler {
+        events: Mutex<Sender<String>>,
+    }
+
+    impl WSHandler for TestHandler {
+        fn who(&self) -> Agent {
+            Agent::Client
+        }
+
+        fn handle_text_msg(&self, msg: String) {
+            self.events.lock().unwrap().send(msg).unwrap();
+        }
+
+        fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+    }
+
+    impl ReconnectHandler for TestHandler {
+        fn on_disconnected(&self, _reason: &str) {
+            let _ = self.events.lock().unwrap().send("disconnected".to_string());
+        }
+
+        fn on_reconnected(&self, _attempts: u32) {
+            let _ = self.events.lock().unwrap().send("reconnected".to_string());
+        }
+    }
+
+    fn handler() -> (TestHandler, Receiver<String>) {
+        let (tx, rx) = channel();
+        (
+            TestHandler {
+                events: Mutex::new(tx),
+            },
+            rx,
+        )
+    }
+
+    fn config() -> ReconnectConfig {
+        ReconnectConfig {
+            initial_delay: Duration::from_millis(300),
+            jitter: 0.0,
+            ..Default::default()
+        }
+    }
+
+    /// Answers the next upgrade on `listener` with a 101
+    fn accept_upgrade(listener: &TcpListener) -> TcpStream {
+        let (mut stream, _) = listener.accept().unwrap();
+        test_common::accept_upgrade(&mut stream, "");
+        stream
+    }
+
+    fn send(stream: &mut TcpStream, data: &[u8], f_type: FrameType) {
+        let df = DataFrame::build(data, f_type, Agent::Server).unwrap();
+        stream.write_all(&Vec::from(df)).unwrap();
+    }
+
+    fn recv_text(stream: &mut TcpStream) -> String {
+        let mut buf = vec![];
+        loop {
+            if let Ok(Some(len)) = DFParser::frame_len(&buf) {
+                let payload = DFParser::parse(&buf[..len], Agent::Server).unwrap();
+                return String::from_utf8(payload.data).unwrap();
+            }
+            let mut data = [0u8; 512];
+            let n = stream.read(&mut data).unwrap();
+            assert!(n > 0);
+            buf.extend_from_slice(&data[..n]);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = ReconnectConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(backoff(&config, 0), Duration::from_millis(100));
+        assert_eq!(backoff(&config, 2), Duration::from_millis(400));
+        assert_eq!(backoff(&config, 10), Duration::from_secs(1));
+        assert_eq!(backoff(&config, u32::MAX), Duration::from_secs(1));
+
+        let jittered = ReconnectConfig {
+            jitter: 0.5,
+            ..config
+        };
+        for _ in 0..100 {
+            let d = backoff(&jittered, 2);
+            assert!(d >= Duration::from_millis(200) && d <= Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn test_reconnects_and_flushes_buffer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+        let (received_tx, received_rx) = channel();
+
+        thread::spawn(move || {
+            let mut first = accept_upgrade(&listener);
+            send(&mut first, b"one", FrameType::Text);
+            drop(first);
+
+            let mut second = accept_upgrade(&listener);
+            received_tx.send(recv_text(&mut second)).unwrap();
+            send(&mut second, b"two", FrameType::Text);
+            let _ = second.read(&mut [0u8; 64]);
+        });
+
+        let (handler, events) = handler();
+        let client =
+            ReconnectingClient::connect(WSClientBuilder::new(&url), config(), handler).unwrap();
+
+        let reader = client.clone();
+        thread::spawn(move || while reader.read().is_ok() {});
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(events.recv_timeout(timeout).unwrap(), "one");
+        assert_eq!(events.recv_timeout(timeout).unwrap(), "disconnected");
+
+        // the reader is waiting out the backoff
+        client.write(b"queued", FrameType::Text).unwrap();
+        assert_eq!(client.buffered(), 1);
+
+        assert_eq!(events.recv_timeout(timeout).unwrap(), "reconnected");
+        assert_eq!(received_rx.recv_timeout(timeout).unwrap(), "queued");
+        assert_eq!(events.recv_timeout(timeout).unwrap(), "two");
+        assert_eq!(client.buffered(), 0);
+        assert!(client.is_connected());
+
+        client.close(1000, "Done").unwrap();
+    }
+
+    #[test]
+    fn test_buffer_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            drop(accept_upgrade(&listener));
+            thread::sleep(Duration::from_secs(5));
+        });
+
+        let (handler, events) = handler();
+        let client = ReconnectingClient::connect(
+            WSClientBuilder::new(&url),
+            ReconnectConfig {
+                max_buffered: 1,
+                ..config()
+            },
+            handler,
+        )
+        .unwrap();
+
+        let reader = client.clone();
+        thread::spawn(move || while reader.read().is_ok() {});
+
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(5)).unwrap(),
+            "disconnected"
+        );
+        client.write(b"kept", FrameType::Text).unwrap();
+        assert!(client.write(b"dropped", FrameType::Text).is_err());
+        client.close(1000, "Done").unwrap();
+    }
+
+    #[test]
+    fn test_normal_close_is_not_retried() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            let mut stream = accept_upgrade(&listener);
+            send(&mut stream, &1000u16.to_be_bytes(), FrameType::Close);
+            let _ = stream.read(&mut [0u8; 64]);
+        });
+
+        let (handler, events) = handler();
+        let client =
+            ReconnectingClient::connect(WSClientBuilder::new(&url), config(), handler).unwrap();
+
+        while client.read().is_ok() {}
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(1)).unwrap(),
+            "disconnected"
+        );
+        assert!(client.read().is_err());
+        assert!(!client.is_connected());
+    }
+
+    #[test]
+    fn test_slow_write_does_not_lock_the_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        // reads the start of a large message and no more, so the rest fills the socket buffers
+        let (started_tx, started) = channel();
+        let (done, done_rx) = channel::<()>();
+        thread::spawn(move || {
+            let mut stream = accept_upgrade(&listener);
+            stream.read_exact(&mut [0u8; 2]).unwrap();
+            started_tx.send(()).unwrap();
+            let _ = done_rx.recv();
+        });
+
+        let (handler, _events) = handler();
+        let client =
+            ReconnectingClient::connect(WSClientBuilder::new(&url), config(), handler).unwrap();
+
+        let writer = client.clone();
+        thread::spawn(move || writer.write(&vec![0u8; 32 * 1024 * 1024], FrameType::Binary));
+        started.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let (tx, rx) = channel();
+        let other = client.clone();
+        thread::spawn(move || tx.send((other.is_connected(), other.buffered())).unwrap());
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), (true, 0));
+        drop(done);
+    }
+}
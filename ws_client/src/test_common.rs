@@ -0,0 +1,43 @@
+//! Handler and server side of the upgrade shared by the tests
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use ws_core::data_frame_tx::Agent;
+use ws_core::http_utils::{parse_request, HeadLimits, Headers};
+use ws_core::{base64, sha1, WSHandler};
+
+/// Ignores every message
+pub(crate) struct TestHandler;
+
+impl WSHandler for TestHandler {
+    fn who(&self) -> Agent {
+        Agent::Client
+    }
+
+    fn handle_text_msg(&self, _msg: String) {}
+
+    fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+}
+
+/// Reads the upgrade request on `stream` and answers it with a 101 and `extra` response
+/// headers, returns the request headers
+pub(crate) fn accept_upgrade(stream: &mut TcpStream, extra: &str) -> Headers {
+    let mut buf = [0u8; 2048];
+    let n = stream.read(&mut buf).unwrap();
+    let headers = parse_request(&buf[..n], &HeadLimits::default())
+        .unwrap()
+        .0
+        .headers;
+
+    let key = headers["sec-websocket-key"].to_string();
+    let accept = base64::encode(&sha1::hash(&(key + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11")));
+    let res = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: {}\r\n{}\r\n",
+        accept, extra
+    );
+    stream.write_all(res.as_bytes()).unwrap();
+    headers
+}
@@ -0,0 +1,122 @@
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Instant;
+use url::Url;
+
+use crate::builder::RequestOptions;
+use crate::client::handshake;
+use crate::errors::ClientError;
+use ws_core::conn_info::ConnectionInfo;
+use ws_core::http_utils::HeadLimits;
+use ws_core::pem::{certs_from_pem, key_from_pem, roots_from_pem};
+use ws_core::rustls::crypto::ring;
+use ws_core::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use ws_core::rustls::{ClientConfig, ClientConnection, RootCertStore, Stream};
+use ws_core::{WSHandler, WSStream};
+
+/// Client config verifying the server against `roots`
+pub fn client_config(roots: RootCertStore) -> Result<Arc<ClientConfig>, String> {
+    match ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+    {
+        Ok(b) => Ok(Arc::new(
+            b.with_root_certificates(roots).with_no_client_auth(),
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Client config trusting only the CA certificates in `ca_pem`, for private or self-signed
+/// server certificates
+pub fn client_config_from_pem(ca_pem: &[u8]) -> Result<Arc<ClientConfig>, String> {
+    client_config(roots_from_pem(ca_pem)?)
+}
+
+/// Client config presenting `cert_chain` signed with `key` to servers requiring mutual TLS
+pub fn client_config_with_cert(
+    roots: RootCertStore,
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<ClientConfig>, String> {
+    let config = match ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+    {
+        Ok(b) => b
+            .with_root_certificates(roots)
+            .with_client_auth_cert(cert_chain, key),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    match config {
+        Ok(c) => Ok(Arc::new(c)),
+        Err(e) => Err(format!("Invalid certificate or key: {}", e)),
+    }
+}
+
+/// Same as `client_config_with_cert` with everything read from PEM
+pub fn client_config_with_cert_from_pem(
+    ca_pem: &[u8],
+    cert_pem: &[u8],
+    key_pem: &[u8],
+) -> Result<Arc<ClientConfig>, String> {
+    client_config_with_cert(
+        roots_from_pem(ca_pem)?,
+        certs_from_pem(cert_pem)?,
+        key_from_pem(key_pem)?,
+    )
+}
+
+/// Client config trusting the Mozilla root store, used for wss:// URLs by default
+pub fn default_client_config() -> Result<Arc<ClientConfig>, String> {
+    client_config(RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    })
+}
+
+/// Runs the TLS handshake for the URL host and then the websocket upgrade inside the session
+pub(crate) fn connect<H>(
+    host: &Url,
+    mut stream: TcpStream,
+    config: Arc<ClientConfig>,
+    handler: Arc<H>,
+    options: &RequestOptions,
+    limits: &HeadLimits,
+    deadline: Option<Instant>,
+) -> Result<WSStream<H>, ClientError>
+where
+    H: WSHandler,
+{
+    let server_name = match host.host_str().map(|h| h.trim_matches(['[', ']'])) {
+        Some(h) => match ServerName::try_from(h.to_string()) {
+            Ok(n) => n,
+            Err(_) => return Err("Invalid server name".to_string().into()),
+        },
+        None => return Err("Invalid host url".to_string().into()),
+    };
+
+    let mut conn = match ClientConnection::new(config, server_name) {
+        Ok(c) => c,
+        Err(e) => return Err(e.to_string().into()),
+    };
+
+    let socket = match stream.try_clone() {
+        Ok(s) => s,
+        Err(e) => return Err(e.to_string().into()),
+    };
+    let (negotiated, rest) = handshake(
+        host,
+        &mut Stream::new(&mut conn, &mut stream),
+        &socket,
+        options,
+        limits,
+        deadline,
+    )?;
+
+    let ws_stream = WSStream::new_tls(stream, conn.into(), handler)?.with_buffered(rest);
+    let info = ConnectionInfo {
+        protocol: negotiated.protocol,
+        extensions: negotiated.extensions,
+        ..ws_stream.info().clone()
+    };
+    Ok(ws_stream.with_info(info))
+}
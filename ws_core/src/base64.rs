@@ -18,7 +18,7 @@ pub fn encode(bin: &[u8]) -> String {
     let mut window = 0_usize;
     for _ in 0..bin_str.len() / 6 {
         let base_idx = usize::from_str_radix(&bin_str[window..window + 6], 2).unwrap();
-        encoded_str.push_str(&BASE64_TBL[base_idx].to_string());
+        encoded_str.push(BASE64_TBL[base_idx]);
         window += 6;
     }
 
@@ -34,7 +34,7 @@ fn handle_final_quant(bin_str: &str, encoded_str: &mut String) {
     final_quant.push_str(&bin_str[(bin_str.len() / 6) * 6..bin_str.len()]);
     final_quant.push_str("0".repeat(6 - bin_str.len() % 6).as_str());
     let final_idx = usize::from_str_radix(&final_quant, 2).unwrap();
-    encoded_str.push_str(&BASE64_TBL[final_idx].to_string());
+    encoded_str.push(BASE64_TBL[final_idx]);
     encoded_str.push_str("=".repeat((6 - (bin_str.len() % 6)) / 2).as_str());
 }
 
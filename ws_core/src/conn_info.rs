@@ -0,0 +1,131 @@
+use std::net::SocketAddr;
+
+/// What is known about the peer of a connection once the upgrade is done
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectionInfo {
+    pub peer_addr: Option<SocketAddr>,
+    /// Certificate the peer presented and that was verified during the TLS handshake
+    pub peer_identity: Option<PeerIdentity>,
+    /// Subprotocol agreed on during the upgrade
+    pub protocol: Option<String>,
+    /// Extensions agreed on during the upgrade, with their parameters
+    pub extensions: Vec<String>,
+    /// Path of the upgrade request with its dot segments resolved, still percent-encoded
+    pub path: String,
+    /// Decoded query parameters of the upgrade request, in order
+    pub query: Vec<(String, String)>,
+    /// Decoded path parameters of the route the connection matched, see `Router`
+    pub params: Vec<(String, String)>,
+    /// Who the server's upgrade authorization accepted
+    pub auth: Option<AuthInfo>,
+}
+
+/// Outcome of authorizing the upgrade, e.g. the user a token belongs to and its roles
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AuthInfo {
+    pub user: String,
+    pub claims: Vec<(String, String)>,
+}
+
+impl AuthInfo {
+    pub fn new(user: &str) -> Self {
+        AuthInfo {
+            user: user.to_string(),
+            claims: vec![],
+        }
+    }
+
+    pub fn claim(mut self, name: &str, value: &str) -> Self {
+        self.claims.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// First value of the claim `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.claims
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl ConnectionInfo {
+    /// First value of the query parameter `name`
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Path parameter `name`, e.g. `id` for the route `/rooms/:id`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Identity carried by a verified peer certificate
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PeerIdentity {
+    /// Distinguished name of the subject, e.g. `CN=billing,O=Example`
+    pub subject: String,
+    /// DNS names, IP addresses, emails and URIs of the subject alternative names
+    pub sans: Vec<String>,
+    /// Lowercase hex SHA-256 of the DER certificate
+    pub fingerprint: String,
+}
+
+#[cfg(feature = "rustls")]
+impl PeerIdentity {
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        use x509_parser::extensions::GeneralName;
+
+        let cert = match x509_parser::parse_x509_certificate(der) {
+            Ok((_, c)) => c,
+            Err(e) => return Err(format!("Invalid certificate: {}", e)),
+        };
+
+        let sans = match cert.subject_alternative_name() {
+            Ok(Some(ext)) => ext
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(n) | GeneralName::RFC822Name(n) | GeneralName::URI(n) => {
+                        Some(n.to_string())
+                    }
+                    GeneralName::IPAddress(ip) => ip_to_string(ip),
+                    _ => None,
+                })
+                .collect(),
+            Ok(None) => vec![],
+            Err(e) => return Err(format!("Invalid subject alternative names: {}", e)),
+        };
+
+        let fingerprint = ring::digest::digest(&ring::digest::SHA256, der)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Ok(PeerIdentity {
+            subject: cert.subject().to_string(),
+            sans,
+            fingerprint,
+        })
+    }
+}
+
+#[cfg(feature = "rustls")]
+fn ip_to_string(ip: &[u8]) -> Option<String> {
+    use std::net::IpAddr;
+
+    match ip.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(ip).unwrap()).to_string()),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(ip).unwrap()).to_string()),
+        _ => None,
+    }
+}
@@ -20,25 +20,83 @@ impl TryFrom<u8> for FrameType {
 /// when the handler is implementation of the trait WSHandler
 pub struct DFParser {}
 
+const TOO_LONG: &str = "Invalid dataframe, payload length too large";
+
 pub struct DFPayload {
     pub data: Vec<u8>,
     pub f_type: FrameType,
+    /// Unset on every fragment of a message but the last one
+    pub fin: bool,
 }
 
 impl DFParser {
+    /// Returns the total length of the first frame in `buf` once the header and the whole
+    /// payload are available, `None` if more bytes need to be read. Fails when the announced
+    /// length cannot be addressed.
+    pub fn frame_len(buf: &[u8]) -> Result<Option<usize>, String> {
+        let (header_len, payload_len) = match DFParser::header(buf)? {
+            Some(h) => h,
+            None => return Ok(None),
+        };
+
+        match header_len.checked_add(payload_len) {
+            Some(total) if buf.len() >= total => Ok(Some(total)),
+            Some(_) => Ok(None),
+            None => Err(TOO_LONG.to_string()),
+        }
+    }
+
+    /// Payload length announced by the first frame in `buf`, known as soon as its header is
+    pub fn payload_len(buf: &[u8]) -> Result<Option<usize>, String> {
+        Ok(DFParser::header(buf)?.map(|(_, payload_len)| payload_len))
+    }
+
+    fn header(buf: &[u8]) -> Result<Option<(usize, usize)>, String> {
+        let (header_len, payload_len) = match DFParser::raw_header(buf) {
+            Some(h) => h,
+            None => return Ok(None),
+        };
+
+        match usize::try_from(payload_len) {
+            Ok(l) => Ok(Some((header_len, l))),
+            Err(_) => Err(TOO_LONG.to_string()),
+        }
+    }
+
+    fn raw_header(buf: &[u8]) -> Option<(usize, u64)> {
+        let m_len = *buf.get(1)?;
+        let mut header_len = 2usize;
+
+        let payload_len = match m_len & 0x7F {
+            ..126 => (m_len & 0x7F) as u64,
+            126 => {
+                header_len += 2;
+                u16::from_be_bytes(buf.get(2..4)?.try_into().unwrap()) as u64
+            }
+            _ => {
+                header_len += 8;
+                u64::from_be_bytes(buf.get(2..10)?.try_into().unwrap())
+            }
+        };
+
+        if m_len & 0x80 == 0x80 {
+            header_len += 4;
+        }
+
+        Some((header_len, payload_len))
+    }
+
     /// Caller needs to create new `buffer` of Vec<u8> and read the stream till the *EOF*
     /// into the `buffer`
     pub fn parse(buf: &[u8], agent: Agent) -> Result<DFPayload, String> {
-        if buf.len() < 3 {
+        if buf.len() < 2 {
             return Err("Minimum frame length is 2 bytes".to_string());
         }
 
-        if buf.first().unwrap() & 0xF0 != 0x80 {
-            return Err(
-                "Fin bit unset or rsv1-3 set, this impl assumes no fragmentation and rsv1-3 unset"
-                    .to_string(),
-            );
+        if buf.first().unwrap() & 0x70 != 0 {
+            return Err("rsv1-3 set, this impl assumes no extensions".to_string());
         }
+        let fin = buf.first().unwrap() & 0x80 == 0x80;
 
         let op_code = buf.first().unwrap() & 0xF;
         let f_type: FrameType = match op_code.try_into() {
@@ -65,6 +123,9 @@ impl DFParser {
             match f_type {
                 FrameType::Continuation | FrameType::Text | FrameType::Binary => (),
                 FrameType::Close | FrameType::Ping | FrameType::Pong => {
+                    if !fin {
+                        return Err("Control frame cannot be fragmented".to_string());
+                    }
                     if m_len & 0x7F > 125 {
                         return Err("Control frame length exceeds 125 bytes".to_string());
                     }
@@ -88,35 +149,47 @@ impl DFParser {
                     None => return Err("Invalid payload, missing payload length".to_string()),
                     Some(l) => {
                         mask_idx = 10;
-                        usize::from_be_bytes(l.try_into().unwrap())
+                        match usize::try_from(u64::from_be_bytes(l.try_into().unwrap())) {
+                            Ok(l) => l,
+                            Err(_) => return Err(TOO_LONG.to_string()),
+                        }
                     }
                 },
             };
 
             let payload: Vec<u8> = match agent {
                 Agent::Server => match buf.get(mask_idx..mask_idx + 4) {
-                    Some(m) => match buf.get(mask_idx + 4..payload_len + mask_idx + 4) {
-                        Some(l) => l
-                            .iter()
-                            .enumerate()
-                            .map(|(i, data)| *data ^ m[i % 4])
-                            .collect(),
-                        None => return Err("Invalid payload from client, incomplete payload".to_string()),
-                    },
+                    Some(m) => {
+                        match buf.get(mask_idx + 4..(mask_idx + 4).saturating_add(payload_len)) {
+                            Some(l) => l
+                                .iter()
+                                .enumerate()
+                                .map(|(i, data)| *data ^ m[i % 4])
+                                .collect(),
+                            None => {
+                                return Err(
+                                    "Invalid payload from client, incomplete payload".to_string()
+                                )
+                            }
+                        }
+                    }
                     None => return Err("Invalid mask".to_string()),
                 },
-                Agent::Client => match buf.get(mask_idx..payload_len) {
+                Agent::Client => match buf.get(mask_idx..mask_idx.saturating_add(payload_len)) {
                     Some(l) => l.to_vec(),
-                    None => return Err("Invalid payload from server, incomplete payload".to_string()),
+                    None => {
+                        return Err("Invalid payload from server, incomplete payload".to_string())
+                    }
                 },
             };
 
             Ok(DFPayload {
                 data: payload,
                 f_type,
+                fin,
             })
         } else {
-            return Err("Invalid dataframe, missing payload length".to_string());
+            Err("Invalid dataframe, missing payload length".to_string())
         }
     }
 }
@@ -70,10 +70,29 @@ impl From<DataFrame> for Vec<u8> {
 
 impl DataFrame {
     pub fn build(payload: &[u8], f_type: FrameType, agent: Agent) -> Result<Self, String> {
+        DataFrame::build_fragment(payload, f_type, true, agent)
+    }
+
+    /// Builds one frame of a fragmented message, the first fragment carries the message type,
+    /// the following ones are `FrameType::Continuation` and the last one has `fin` set
+    pub fn build_fragment(
+        payload: &[u8],
+        f_type: FrameType,
+        fin: bool,
+        agent: Agent,
+    ) -> Result<Self, String> {
         validate_payload(payload, f_type)?;
+        if !fin
+            && !matches!(
+                f_type,
+                FrameType::Continuation | FrameType::Text | FrameType::Binary
+            )
+        {
+            return Err("Control frame cannot be fragmented".to_string());
+        }
 
         let mut df = DataFrame {
-            fin: true,
+            fin,
             rsv1: false,
             rsv2: false,
             rsv3: false,
@@ -121,7 +140,7 @@ impl DataFrame {
 
 fn get_masking_key() -> [u8; 4] {
     let mut key = [0u8; 4];
-    rand::thread_rng().fill_bytes(&mut key);
+    rand::rng().fill_bytes(&mut key);
     key
 }
 
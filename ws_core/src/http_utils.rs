@@ -1,32 +1,360 @@
-use std::collections::HashMap;
-
-pub fn validate_http_version(p0: &str) -> Result<(), &str> {
-    match p0.splitn(2, "/").last() {
-        Some(v) => match v.parse::<f32>() {
-            Ok(version) => {
-                if version < 1.1 {
-                    Err("Invalid HTTP version")
-                } else {
-                    Ok(())
-                }
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::io::{ErrorKind, Read};
+use std::net::TcpStream;
+use std::ops::Index;
+use std::time::Instant;
+
+/// Bounds on the head of a request or response, the lines before the body
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HeadLimits {
+    /// Bytes up to and including the blank line ending the head
+    pub max_size: usize,
+    pub max_headers: usize,
+}
+
+impl Default for HeadLimits {
+    fn default() -> Self {
+        HeadLimits {
+            max_size: 16 * 1024,
+            max_headers: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HeadError {
+    /// No `\r\n\r\n` yet, more bytes are needed
+    Incomplete,
+    /// The head is longer than `HeadLimits::max_size`
+    TooLarge(usize),
+    /// More header fields than `HeadLimits::max_headers`
+    TooManyHeaders(usize),
+    /// Line, counted from 1, ending in a bare `\n` instead of `\r\n`
+    BareLf(usize),
+    InvalidStartLine(String),
+    InvalidVersion(String),
+    /// Line, counted from 1, and what is wrong with it
+    InvalidHeader(usize, String),
+    /// The deadline passed before the head was complete
+    TimedOut,
+    /// The peer closed the connection before the head was complete
+    Closed,
+    Io(String),
+}
+
+impl fmt::Display for HeadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadError::Incomplete => write!(f, "Incomplete HTTP head"),
+            HeadError::TooLarge(max) => write!(f, "HTTP head larger than {} bytes", max),
+            HeadError::TooManyHeaders(max) => write!(f, "More than {} header fields", max),
+            HeadError::BareLf(line) => write!(f, "Line {} does not end with CRLF", line),
+            HeadError::InvalidStartLine(l) => write!(f, "Invalid start line {:?}", l),
+            HeadError::InvalidVersion(v) => write!(f, "Invalid HTTP version {:?}", v),
+            HeadError::InvalidHeader(line, e) => {
+                write!(f, "Invalid header on line {}: {}", line, e)
             }
-            Err(_) => Err("Invalid HTTP version"),
+            HeadError::TimedOut => write!(f, "Timed out reading HTTP head"),
+            HeadError::Closed => write!(f, "Connection closed before the end of the HTTP head"),
+            HeadError::Io(e) => write!(f, "Failed to read HTTP head: {}", e),
+        }
+    }
+}
+
+/// Header fields in the order received, looked up by case-insensitive name. A name can appear
+/// more than once.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Headers::default()
+    }
+
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// First value of `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of `name`, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Elements of a comma separated list like `Connection: keep-alive, Upgrade`, across every
+    /// field named `name`
+    pub fn tokens(&self, name: &str) -> Vec<String> {
+        self.get_all(name)
+            .flat_map(|v| v.split(','))
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    /// Whether the list `name` has `token`, case-insensitive
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.tokens(name)
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(token))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// First value of `name`, panics when there is none
+impl Index<&str> for Headers {
+    type Output = str;
+
+    fn index(&self, name: &str) -> &str {
+        match self.get(name) {
+            Some(v) => v,
+            None => panic!("No header {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RequestHead {
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub headers: Headers,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResponseHead {
+    pub version: String,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+}
+
+/// Parses the head at the start of `buf`, returning it with its length so whatever follows can
+/// be read as the body
+pub fn parse_request(buf: &[u8], limits: &HeadLimits) -> Result<(RequestHead, usize), HeadError> {
+    let (lines, len) = split_head(buf, limits)?;
+
+    let start: Vec<&str> = lines[0].split(' ').collect();
+    if start.len() != 3 || start.iter().any(|p| p.is_empty()) || !is_token(start[0]) {
+        return Err(HeadError::InvalidStartLine(lines[0].to_string()));
+    }
+    validate_version(start[2])?;
+
+    Ok((
+        RequestHead {
+            method: start[0].to_string(),
+            target: start[1].to_string(),
+            version: start[2].to_string(),
+            headers: parse_fields(&lines[1..], limits)?,
         },
-        None => Err("Invalid Request"),
-    }
-}
-
-pub fn parse_headers(h_lines: &[String]) -> HashMap<String, String> {
-    h_lines
-        .iter()
-        .skip(1)
-        .map(|d| {
-            let mut header = d.splitn(2, ":");
-            (
-                header.next().unwrap().trim().to_ascii_lowercase(),
-                header.next().unwrap().trim().to_string(),
-            )
-        })
-        .filter(|(k, v)| !k.is_empty() && !v.is_empty())
-        .collect()
+        len,
+    ))
+}
+
+/// Same as `parse_request` for a response
+pub fn parse_response(buf: &[u8], limits: &HeadLimits) -> Result<(ResponseHead, usize), HeadError> {
+    let (lines, len) = split_head(buf, limits)?;
+
+    let start: Vec<&str> = lines[0].splitn(3, ' ').collect();
+    let status = match start.get(1) {
+        Some(s) if s.len() == 3 => s.parse::<u16>().ok(),
+        _ => None,
+    };
+    let status = match status {
+        Some(s) => s,
+        None => return Err(HeadError::InvalidStartLine(lines[0].to_string())),
+    };
+    validate_version(start[0])?;
+
+    Ok((
+        ResponseHead {
+            version: start[0].to_string(),
+            status,
+            reason: start.get(2).copied().unwrap_or_default().to_string(),
+            headers: parse_fields(&lines[1..], limits)?,
+        },
+        len,
+    ))
+}
+
+/// `parse_request` or `parse_response`
+pub type ParseHead<T> = fn(&[u8], &HeadLimits) -> Result<(T, usize), HeadError>;
+
+/// Reads from `stream` until `parse` finds a complete head, within `limits` and before
+/// `deadline`. Returns the head with the bytes read past it, which belong to whatever follows.
+/// `socket` is the connection under `stream`, its read timeout is changed to enforce the deadline.
+pub fn read_head<S, T>(
+    stream: &mut S,
+    socket: &TcpStream,
+    limits: &HeadLimits,
+    deadline: Option<Instant>,
+    parse: ParseHead<T>,
+) -> Result<(T, Vec<u8>), HeadError>
+where
+    S: Read,
+{
+    let mut buf = vec![];
+    let mut data = [0u8; 1024];
+    loop {
+        if let Err(e) = apply_deadline(socket, deadline) {
+            return Err(io_error(e));
+        }
+        match stream.read(&mut data) {
+            Ok(0) => return Err(HeadError::Closed),
+            Ok(n) => buf.extend_from_slice(&data[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io_error(e)),
+        }
+
+        match parse(&buf, limits) {
+            Ok((head, len)) => return Ok((head, buf.split_off(len))),
+            Err(HeadError::Incomplete) => (),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Limits the next read on `socket` to the time left until `deadline`, fails with
+/// `ErrorKind::TimedOut` once it has passed
+pub fn apply_deadline(socket: &TcpStream, deadline: Option<Instant>) -> io::Result<()> {
+    let deadline = match deadline {
+        Some(d) => d,
+        None => return Ok(()),
+    };
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+        return Err(io::Error::from(ErrorKind::TimedOut));
+    }
+    socket.set_read_timeout(Some(left))
+}
+
+fn io_error(e: io::Error) -> HeadError {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => HeadError::TimedOut,
+        _ => HeadError::Io(e.to_string()),
+    }
+}
+
+/// Lines of the head without their CRLF, and the length of the head with the blank line
+fn split_head<'a>(buf: &'a [u8], limits: &HeadLimits) -> Result<(Vec<&'a str>, usize), HeadError> {
+    let end = buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4);
+
+    // a head ending in bare LFs would otherwise look incomplete until it hits the limit
+    if let Some(line) = bare_lf_line(&buf[..end.unwrap_or(buf.len())]) {
+        return Err(HeadError::BareLf(line));
+    }
+
+    let len = match end {
+        Some(len) if len <= limits.max_size => len,
+        Some(_) => return Err(HeadError::TooLarge(limits.max_size)),
+        None if buf.len() >= limits.max_size => return Err(HeadError::TooLarge(limits.max_size)),
+        None => return Err(HeadError::Incomplete),
+    };
+
+    let head = match std::str::from_utf8(&buf[..len - 4]) {
+        Ok(h) => h,
+        Err(_) => return Err(HeadError::InvalidStartLine("Not UTF-8".to_string())),
+    };
+
+    let lines: Vec<&str> = head.split("\r\n").collect();
+    if let Some(i) = lines.iter().position(|l| l.contains('\r')) {
+        return Err(HeadError::InvalidHeader(i + 1, "Bare CR".to_string()));
+    }
+    Ok((lines, len))
+}
+
+/// Number of the first line ending in `\n` without `\r`
+fn bare_lf_line(buf: &[u8]) -> Option<usize> {
+    let mut line = 1;
+    for (i, b) in buf.iter().enumerate() {
+        if *b == b'\n' {
+            if i == 0 || buf[i - 1] != b'\r' {
+                return Some(line);
+            }
+            line += 1;
+        }
+    }
+    None
+}
+
+fn parse_fields(lines: &[&str], limits: &HeadLimits) -> Result<Headers, HeadError> {
+    if lines.len() > limits.max_headers {
+        return Err(HeadError::TooManyHeaders(limits.max_headers));
+    }
+
+    let mut headers = Headers::new();
+    for (i, line) in lines.iter().enumerate() {
+        // after the start line
+        let line_no = i + 2;
+        if line.starts_with([' ', '\t']) {
+            return Err(HeadError::InvalidHeader(
+                line_no,
+                "Obsolete line folding".to_string(),
+            ));
+        }
+
+        let (name, value) = match line.split_once(':') {
+            Some(h) => h,
+            None => {
+                return Err(HeadError::InvalidHeader(
+                    line_no,
+                    "Missing colon".to_string(),
+                ))
+            }
+        };
+        if !is_token(name) {
+            return Err(HeadError::InvalidHeader(
+                line_no,
+                format!("Invalid field name {:?}", name),
+            ));
+        }
+        headers.append(name, value.trim_matches([' ', '\t']));
+    }
+    Ok(headers)
+}
+
+/// HTTP/1.1 or a later 1.x
+fn validate_version(version: &str) -> Result<(), HeadError> {
+    let minor = version
+        .strip_prefix("HTTP/1.")
+        .and_then(|m| m.parse::<u8>().ok());
+    match minor {
+        Some(m) if m >= 1 => Ok(()),
+        _ => Err(HeadError::InvalidVersion(version.to_string())),
+    }
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
 }
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+/// Keepalive settings of a connection, a Ping is sent every `interval` the peer has been quiet
+/// and the connection is dropped after `max_missed_pongs` pings went unanswered. When
+/// `idle_timeout` is set the connection is closed with 1001 once no text or binary message has
+/// been sent or received for that long.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeepAlive {
+    pub interval: Duration,
+    pub max_missed_pongs: u32,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive {
+            interval: Duration::from_secs(30),
+            max_missed_pongs: 2,
+            idle_timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum KeepAliveAction {
+    Nothing,
+    Ping(Vec<u8>),
+    /// No message exchanged within the idle timeout, close with 1001
+    Idle,
+    /// Too many pings left unanswered, the peer is gone (1006)
+    Dead,
+}
+
+pub(crate) struct KeepAliveState {
+    config: Option<KeepAlive>,
+    last_heard: Instant,
+    last_data: Instant,
+    last_ping: Option<Instant>,
+    pending_ping: Option<Vec<u8>>,
+    ping_seq: u64,
+    missed_pongs: u32,
+}
+
+impl KeepAliveState {
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+        KeepAliveState {
+            config: None,
+            last_heard: now,
+            last_data: now,
+            last_ping: None,
+            pending_ping: None,
+            ping_seq: 0,
+            missed_pongs: 0,
+        }
+    }
+
+    pub(crate) fn configure(&mut self, config: Option<KeepAlive>) {
+        let now = Instant::now();
+        self.config = config;
+        self.last_heard = now;
+        self.last_data = now;
+        self.last_ping = None;
+        self.pending_ping = None;
+        self.missed_pongs = 0;
+    }
+
+    /// Any frame received from the peer
+    pub(crate) fn on_frame(&mut self, now: Instant) {
+        self.last_heard = now;
+    }
+
+    /// A text or binary message sent or received
+    pub(crate) fn on_data(&mut self, now: Instant) {
+        self.last_data = now;
+    }
+
+    /// When the last frame was received from the peer
+    pub(crate) fn last_heard(&self) -> Instant {
+        self.last_heard
+    }
+
+    pub(crate) fn on_pong(&mut self, payload: &[u8]) {
+        if self.pending_ping.as_deref() == Some(payload) {
+            self.pending_ping = None;
+            self.missed_pongs = 0;
+        }
+    }
+
+    /// Time left until the next keepalive check is due, used as the read timeout
+    pub(crate) fn next_deadline(&self, now: Instant) -> Option<Duration> {
+        let config = self.config?;
+
+        let ping_at = self
+            .last_heard
+            .max(self.last_ping.unwrap_or(self.last_heard))
+            + config.interval;
+        let deadline = match config.idle_timeout {
+            Some(idle) => ping_at.min(self.last_data + idle),
+            None => ping_at,
+        };
+
+        Some(
+            deadline
+                .saturating_duration_since(now)
+                .max(Duration::from_millis(1)),
+        )
+    }
+
+    pub(crate) fn poll(&mut self, now: Instant) -> KeepAliveAction {
+        let config = match self.config {
+            Some(c) => c,
+            None => return KeepAliveAction::Nothing,
+        };
+
+        if let Some(idle) = config.idle_timeout {
+            if now.duration_since(self.last_data) >= idle {
+                return KeepAliveAction::Idle;
+            }
+        }
+
+        let quiet_since = self
+            .last_heard
+            .max(self.last_ping.unwrap_or(self.last_heard));
+        if now.duration_since(quiet_since) < config.interval {
+            return KeepAliveAction::Nothing;
+        }
+
+        if self.pending_ping.is_some() {
+            self.missed_pongs += 1;
+            if self.missed_pongs >= config.max_missed_pongs {
+                return KeepAliveAction::Dead;
+            }
+        }
+
+        self.ping_seq += 1;
+        let payload = self.ping_seq.to_be_bytes().to_vec();
+        self.pending_ping = Some(payload.clone());
+        self.last_ping = Some(now);
+
+        KeepAliveAction::Ping(payload)
+    }
+}
@@ -1,21 +1,71 @@
 pub mod base64;
+pub mod conn_info;
 pub mod data_frame_rx;
 pub mod data_frame_tx;
 pub mod http_utils;
+pub mod keepalive;
+#[cfg(feature = "rustls")]
+pub mod pem;
+mod rtt;
+pub mod send_queue;
 pub mod sha1;
+pub mod socket;
 mod tests;
+mod transport;
+mod writer;
 
-use crate::data_frame_rx::DFParser;
-use crate::data_frame_tx::DataFrame;
+#[cfg(feature = "rustls")]
+pub use rustls;
+
+use crate::conn_info::{ConnectionInfo, PeerIdentity};
+use crate::data_frame_rx::{DFParser, DFPayload};
+use crate::keepalive::{KeepAlive, KeepAliveAction, KeepAliveState};
+use crate::rtt::RttState;
+use crate::send_queue::{
+    QueueError, SendQueue, SendQueueConfig, SendQueueStats, CLOSE_DRAIN_TIMEOUT,
+};
+use crate::transport::Transport;
+use crate::writer::FrameWriter;
 use data_frame_tx::{Agent, FrameType};
-use std::io::{Read, Write};
+use std::io::ErrorKind;
 use std::net::{Shutdown, TcpStream};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest frame accepted from the peer unless `WSStream::set_max_frame_size` says otherwise
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Largest message, its fragments put together, accepted from the peer unless
+/// `WSStream::set_max_message_size` says otherwise
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
 
 pub trait WSHandler {
     fn who(&self) -> Agent;
     fn handle_text_msg(&self, msg: String);
     fn handle_bin_msg(&self, msg: Vec<u8>);
+    /// Called by the server once a connection is upgraded, before any message of it is handled
+    fn on_open(&self, _info: &ConnectionInfo) {}
+}
+
+/// Lets handlers of different types share a `WSStream<Box<dyn WSHandler + Send + Sync>>`
+impl<T: WSHandler + ?Sized> WSHandler for Box<T> {
+    fn who(&self) -> Agent {
+        (**self).who()
+    }
+
+    fn handle_text_msg(&self, msg: String) {
+        (**self).handle_text_msg(msg)
+    }
+
+    fn handle_bin_msg(&self, msg: Vec<u8>) {
+        (**self).handle_bin_msg(msg)
+    }
+
+    fn on_open(&self, info: &ConnectionInfo) {
+        (**self).on_open(info)
+    }
 }
 
 #[derive(Eq, PartialEq, Copy, Clone)]
@@ -25,9 +75,38 @@ pub enum ConnectionStatus {
     Closed,
 }
 
+/// State shared by every clone of a `WSStream`
+struct Shared {
+    read_buf: Mutex<Vec<u8>>,
+    keepalive: Mutex<KeepAliveState>,
+    rtt: Mutex<RttState>,
+    pong_received: Condvar,
+    send_queue: OnceLock<Arc<SendQueue>>,
+    transport: Arc<Transport>,
+    writer: Arc<FrameWriter>,
+    /// Type and payload of a fragmented message still being received
+    partial: Mutex<Option<(FrameType, Vec<u8>)>>,
+    /// Largest frame and message accepted from the peer
+    max_frame_size: AtomicUsize,
+    max_message_size: AtomicUsize,
+    read_timeout: Mutex<Option<Duration>>,
+    /// Status code of the close frame received from the peer
+    peer_close: Mutex<Option<u16>>,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if let Some(queue) = self.send_queue.get() {
+            queue.close();
+        }
+    }
+}
+
 pub struct WSStream<H> {
     pub stream: TcpStream,
     pub handler: Arc<H>,
+    info: Arc<ConnectionInfo>,
+    shared: Arc<Shared>,
 }
 
 impl<H> Clone for WSStream<H> {
@@ -35,6 +114,8 @@ impl<H> Clone for WSStream<H> {
         WSStream {
             stream: self.stream.try_clone().unwrap(),
             handler: self.handler.clone(),
+            info: self.info.clone(),
+            shared: self.shared.clone(),
         }
     }
 }
@@ -43,77 +124,495 @@ impl<H> WSStream<H>
 where
     H: WSHandler,
 {
-    pub fn read(&mut self) -> Result<(), String> {
-        let mut data = [0u8; 512];
-        let r_size = match self.stream.read(&mut data) {
-            Ok(n) => n,
-            Err(e) => {
-                println!("Failed to read: {:?}", e);
-                return Err("Read failed".to_string());
+    pub fn new(stream: TcpStream, handler: Arc<H>) -> Self {
+        WSStream::with_transport(stream, Transport::plain(), None, handler)
+    }
+
+    /// Wraps a socket whose TLS handshake is complete, any plaintext `conn` already decrypted,
+    /// like frames sent right after the upgrade response, is read first. The certificate the
+    /// peer presented becomes its `peer_identity`, it fails when that certificate cannot be
+    /// read.
+    #[cfg(feature = "rustls")]
+    pub fn new_tls(
+        stream: TcpStream,
+        conn: rustls::Connection,
+        handler: Arc<H>,
+    ) -> Result<Self, String> {
+        let peer_identity = match conn.peer_certificates().and_then(|certs| certs.first()) {
+            Some(cert) => match PeerIdentity::from_der(cert) {
+                Ok(identity) => Some(identity),
+                Err(e) => return Err(format!("Peer certificate not readable: {}", e)),
+            },
+            None => None,
+        };
+        Ok(WSStream::with_transport(
+            stream,
+            Transport::tls(conn),
+            peer_identity,
+            handler,
+        ))
+    }
+
+    fn with_transport(
+        stream: TcpStream,
+        transport: Transport,
+        peer_identity: Option<PeerIdentity>,
+        handler: Arc<H>,
+    ) -> Self {
+        let agent = handler.who();
+        let transport = Arc::new(transport);
+        let info = ConnectionInfo {
+            peer_addr: stream.peer_addr().ok(),
+            peer_identity,
+            protocol: None,
+            extensions: vec![],
+            path: String::new(),
+            query: vec![],
+            params: vec![],
+            auth: None,
+        };
+        WSStream {
+            stream,
+            handler,
+            info: Arc::new(info),
+            shared: Arc::new(Shared {
+                read_buf: Mutex::new(vec![]),
+                keepalive: Mutex::new(KeepAliveState::new()),
+                rtt: Mutex::new(RttState::new()),
+                pong_received: Condvar::new(),
+                send_queue: OnceLock::new(),
+                transport: transport.clone(),
+                writer: Arc::new(FrameWriter::new(agent, transport)),
+                partial: Mutex::new(None),
+                max_frame_size: AtomicUsize::new(DEFAULT_MAX_FRAME_SIZE),
+                max_message_size: AtomicUsize::new(DEFAULT_MAX_MESSAGE_SIZE),
+                read_timeout: Mutex::new(None),
+                peer_close: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Replaces the metadata of the connection, clones made before keep the old one
+    pub fn with_info(mut self, info: ConnectionInfo) -> Self {
+        self.info = Arc::new(info);
+        self
+    }
+
+    /// Seeds the read buffer with `data`, like bytes received right after the handshake, so it
+    /// is parsed as frames before anything else is read
+    pub fn with_buffered(self, data: Vec<u8>) -> Self {
+        self.shared.read_buf.lock().unwrap().splice(..0, data);
+        self
+    }
+
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    /// Enables or disables keepalive pings, `read` has to be called in a loop for the pings to be
+    /// sent and the timeouts to be detected
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepAlive>) -> Result<(), String> {
+        self.shared.keepalive.lock().unwrap().configure(keepalive);
+        if keepalive.is_none() {
+            if let Err(e) = self.stream.set_read_timeout(None) {
+                return Err(e.to_string());
             }
+        }
+        Ok(())
+    }
+
+    /// Routes every text and binary write through a bounded queue drained by a writer thread,
+    /// so a slow peer only blocks the callers as the overflow policy says. Control frames skip
+    /// the queue. Can only be set once.
+    pub fn set_send_queue(&mut self, config: SendQueueConfig) -> Result<(), String> {
+        let queue = Arc::new(SendQueue::new(config));
+        if self.shared.send_queue.set(queue.clone()).is_err() {
+            return Err("Send queue already set".to_string());
+        }
+
+        let stream = match self.stream.try_clone() {
+            Ok(s) => s,
+            Err(e) => return Err(e.to_string()),
         };
+        let writer = self.shared.writer.clone();
 
-        let payload = match DFParser::parse(&data[..r_size], self.handler.who()) {
-            Ok(p) => p,
-            Err(e) => {
-                self.shutdown("Invalid data")?;
-                return Err(e);
+        thread::spawn(move || {
+            while let Some((f_type, payload)) = queue.pop() {
+                let res = writer.write_message(&stream, &payload, f_type);
+                queue.written();
+                if let Err(e) = res {
+                    println!("Failed to write: {}", e);
+                    let unsent = queue.close_and_drain(Duration::ZERO);
+                    if unsent > 0 {
+                        println!("Dropped {} queued messages", unsent);
+                    }
+                    break;
+                }
             }
+        });
+
+        Ok(())
+    }
+
+    /// Text and binary messages larger than `size` bytes are sent as fragments, letting pongs,
+    /// pings and close frames through in between. 64 KiB by default, 0 disables fragmentation.
+    pub fn set_fragment_size(&mut self, size: usize) {
+        self.shared.writer.set_fragment_size(size);
+    }
+
+    /// Frames announcing a payload larger than `size` bytes close the connection with 1009
+    /// before their payload is read. `DEFAULT_MAX_FRAME_SIZE` by default, 0 sets it back.
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        let size = match size {
+            0 => DEFAULT_MAX_FRAME_SIZE,
+            s => s,
+        };
+        self.shared.max_frame_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Messages larger than `size` bytes once their fragments are put together close the
+    /// connection with 1009. `DEFAULT_MAX_MESSAGE_SIZE` by default, 0 sets it back.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        let size = match size {
+            0 => DEFAULT_MAX_MESSAGE_SIZE,
+            s => s,
+        };
+        self.shared.max_message_size.store(size, Ordering::Relaxed);
+    }
+
+    /// `read` fails once nothing has been received from the peer for `timeout`
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), String> {
+        *self.shared.read_timeout.lock().unwrap() = timeout;
+        if let Err(e) = self.stream.set_read_timeout(timeout) {
+            return Err(e.to_string());
+        }
+        Ok(())
+    }
+
+    /// Depth of the send queue, `None` when writes go straight to the stream
+    pub fn send_queue_stats(&self) -> Option<SendQueueStats> {
+        self.shared.send_queue.get().map(|q| q.stats())
+    }
+
+    pub fn read(&mut self) -> Result<(), String> {
+        let payload = match self.next_frame()? {
+            Some(p) => p,
+            None => return self.keepalive_tick(),
         };
 
+        let now = Instant::now();
+        self.shared.keepalive.lock().unwrap().on_frame(now);
+
         match payload.f_type {
-            FrameType::Continuation | FrameType::Text => {
-                let msg = match String::from_utf8(payload.data) {
-                    Ok(s) => s,
-                    Err(_) => {
-                        self.shutdown("Invalid data")?;
-                        return Err("Invalid utf8 string payload".to_string());
-                    }
+            FrameType::Continuation | FrameType::Text | FrameType::Binary => {
+                let (f_type, data) = match self.assemble(payload)? {
+                    Some(m) => m,
+                    None => return self.keepalive_tick(),
                 };
-                self.handler.handle_text_msg(msg)
+
+                self.shared.keepalive.lock().unwrap().on_data(now);
+                if f_type == FrameType::Binary {
+                    self.handler.handle_bin_msg(data)
+                } else {
+                    let msg = match String::from_utf8(data) {
+                        Ok(s) => s,
+                        Err(_) => {
+                            self.close(1007, "Invalid data")?;
+                            return Err("Invalid utf8 string payload".to_string());
+                        }
+                    };
+                    self.handler.handle_text_msg(msg)
+                }
             }
-            FrameType::Binary => self.handler.handle_bin_msg(payload.data),
-            FrameType::Close | FrameType::Ping | FrameType::Pong => {
-                if payload.f_type == FrameType::Close {
-                    self.shutdown("Close accepted")?
+            FrameType::Close => {
+                let code = payload
+                    .data
+                    .get(..2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]));
+                *self.shared.peer_close.lock().unwrap() = code;
+
+                // echoes the status code, a close frame without one gets an empty answer
+                let reply = code.map_or(vec![], |c| c.to_be_bytes().to_vec());
+                self.drain_send_queue();
+                self.write(&reply, FrameType::Close)?;
+                self.shutdown_stream()?
+            }
+            FrameType::Ping => self.write(&payload.data, FrameType::Pong)?,
+            FrameType::Pong => {
+                self.shared.keepalive.lock().unwrap().on_pong(&payload.data);
+                if self.shared.rtt.lock().unwrap().on_pong(&payload.data, now) {
+                    self.shared.pong_received.notify_all();
                 }
+            }
+        };
 
-                let msg = match String::from_utf8(payload.data) {
-                    Ok(s) => s,
-                    Err(_) => {
-                        self.shutdown("Invalid data")?;
-                        return Err("Invalid utf8 string payload".to_string());
-                    }
-                };
+        self.keepalive_tick()
+    }
+
+    pub fn write(&mut self, data: &[u8], f_type: FrameType) -> Result<(), String> {
+        if matches!(f_type, FrameType::Close | FrameType::Ping | FrameType::Pong) {
+            return self.shared.writer.write_control(&self.stream, data, f_type);
+        }
 
-                if payload.f_type == FrameType::Ping {
-                    self.write(msg.as_bytes(), FrameType::Pong)?;
+        if let Some(queue) = self.shared.send_queue.get().cloned() {
+            match queue.push(f_type, data.to_vec()) {
+                Ok(()) => (),
+                Err(QueueError::Closed) => return Err("Send queue closed".to_string()),
+                Err(QueueError::Overflow(code)) => {
+                    // the peer is behind already, so the queue is not waited for
+                    let unsent = queue.close_and_drain(Duration::ZERO);
+                    println!("Send queue full, {} queued messages dropped", unsent);
+                    self.close(code, "Send queue full")?;
+                    return Err(format!("Send queue full, closed with {}", code));
                 }
             }
-        };
+        } else {
+            self.shared
+                .writer
+                .write_message(&self.stream, data, f_type)?;
+        }
 
+        if f_type == FrameType::Text || f_type == FrameType::Binary {
+            self.shared
+                .keepalive
+                .lock()
+                .unwrap()
+                .on_data(Instant::now());
+        }
         Ok(())
     }
-    pub fn write(&mut self, data: &[u8], f_type: FrameType) -> Result<(), String> {
-        let df = match DataFrame::build(data, f_type, self.handler.who()) {
-            Ok(d) => d,
-            Err(e) => return Err(e),
+
+    /// Sends a ping and waits up to `timeout` for the matching pong, returning the round-trip
+    /// time. Pongs are picked up by `read`, so the stream has to be read on another thread
+    pub fn ping(&mut self, timeout: Duration) -> Result<Duration, String> {
+        let payload = {
+            let mut rtt = self.shared.rtt.lock().unwrap();
+            let payload = rtt.next_payload();
+            rtt.on_ping_sent(&payload, Instant::now());
+            payload
         };
 
-        let d = Vec::from(df);
+        if let Err(e) = self.write(&payload, FrameType::Ping) {
+            self.shared.rtt.lock().unwrap().forget(&payload);
+            return Err(e);
+        }
 
-        match self.stream.write_all(&d) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.to_string()),
+        let deadline = Instant::now() + timeout;
+        let mut rtt = self.shared.rtt.lock().unwrap();
+        loop {
+            if let Some(d) = rtt.take_answer(&payload) {
+                return Ok(d);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                rtt.forget(&payload);
+                return Err("Ping timed out".to_string());
+            }
+
+            rtt = self
+                .shared
+                .pong_received
+                .wait_timeout(rtt, deadline - now)
+                .unwrap()
+                .0;
         }
     }
 
+    /// Status code the peer closed the connection with, `None` until its close frame is read
+    /// or when it had no code
+    pub fn peer_close_code(&self) -> Option<u16> {
+        *self.shared.peer_close.lock().unwrap()
+    }
+
+    /// Smoothed round-trip time of the pings answered so far, keepalive pings included
+    pub fn rtt(&self) -> Option<Duration> {
+        self.shared.rtt.lock().unwrap().srtt()
+    }
+
+    /// Closes normally, with 1000 and `msg` as the reason
     pub fn shutdown(&mut self, msg: &str) -> Result<(), String> {
-        self.write(msg.as_bytes(), FrameType::Close)?;
+        self.close(1000, msg)
+    }
+
+    /// Sends a close frame with the status `code` and `reason` and shuts the stream down. With
+    /// a send queue the messages already queued are written first, for up to
+    /// `CLOSE_DRAIN_TIMEOUT`. Any still queued after that are discarded and counted in
+    /// `SendQueueStats::dropped`.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), String> {
+        self.drain_send_queue();
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        self.write(&payload, FrameType::Close)?;
+        self.shutdown_stream()
+    }
+
+    /// Gives the writer thread `CLOSE_DRAIN_TIMEOUT` to write the queued messages before the
+    /// close frame goes out
+    fn drain_send_queue(&self) {
+        if let Some(queue) = self.shared.send_queue.get() {
+            let unsent = queue.close_and_drain(CLOSE_DRAIN_TIMEOUT);
+            if unsent > 0 {
+                println!("Closing with {} queued messages unsent", unsent);
+            }
+        }
+    }
+
+    fn shutdown_stream(&mut self) -> Result<(), String> {
+        self.shared.transport.close(&self.stream);
         match self.stream.shutdown(Shutdown::Both) {
-            Ok(_) => (),
-            Err(e) => return Err(e.to_string()),
-        };
-        Ok(())
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Collects the fragments of a message, returns its type and payload once the final one
+    /// arrived
+    fn assemble(&mut self, payload: DFPayload) -> Result<Option<(FrameType, Vec<u8>)>, String> {
+        let mut partial = self.shared.partial.lock().unwrap();
+
+        let max_message = self.shared.max_message_size.load(Ordering::Relaxed);
+        let received = partial.as_ref().map_or(0, |(_, data)| data.len());
+        if received.saturating_add(payload.data.len()) > max_message {
+            *partial = None;
+            drop(partial);
+            self.close(1009, "Message too big")?;
+            return Err(format!(
+                "Message larger than {} bytes, closed with 1009",
+                max_message
+            ));
+        }
+
+        match (payload.f_type, partial.as_mut()) {
+            (FrameType::Continuation, Some((_, data))) => data.extend(payload.data),
+            (FrameType::Continuation, None) => {
+                drop(partial);
+                self.close(1002, "Invalid data")?;
+                return Err("Continuation frame without a message to continue".to_string());
+            }
+            (_, Some(_)) => {
+                drop(partial);
+                self.close(1002, "Invalid data")?;
+                return Err("New message started before the last one finished".to_string());
+            }
+            (f_type, None) => *partial = Some((f_type, payload.data)),
+        }
+
+        if payload.fin {
+            Ok(partial.take())
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads from the stream until a complete frame is buffered, `None` when the read timed out
+    /// because a keepalive check is due
+    fn next_frame(&mut self) -> Result<Option<DFPayload>, String> {
+        loop {
+            let parsed = {
+                let mut buf = self.shared.read_buf.lock().unwrap();
+                DFParser::payload_len(&buf).and_then(|announced| {
+                    let len = DFParser::frame_len(&buf)?;
+                    Ok((
+                        announced,
+                        len.map(|len| buf.drain(..len).collect::<Vec<u8>>()),
+                    ))
+                })
+            };
+            let (announced, frame) = match parsed {
+                Ok(p) => p,
+                Err(e) => {
+                    self.close(1009, "Frame too big")?;
+                    return Err(e);
+                }
+            };
+
+            let max_frame = self.shared.max_frame_size.load(Ordering::Relaxed);
+            if announced.is_some_and(|l| l > max_frame) {
+                self.close(1009, "Frame too big")?;
+                return Err(format!(
+                    "Frame larger than {} bytes, closed with 1009",
+                    max_frame
+                ));
+            }
+
+            if let Some(frame) = frame {
+                return match DFParser::parse(&frame, self.handler.who()) {
+                    Ok(p) => Ok(Some(p)),
+                    Err(e) => {
+                        self.close(1002, "Invalid data")?;
+                        Err(e)
+                    }
+                };
+            }
+
+            let now = Instant::now();
+            let (mut timeout, read_deadline) = {
+                let keepalive = self.shared.keepalive.lock().unwrap();
+                let read_deadline = self
+                    .shared
+                    .read_timeout
+                    .lock()
+                    .unwrap()
+                    .map(|t| keepalive.last_heard() + t);
+                (keepalive.next_deadline(now), read_deadline)
+            };
+            if let Some(deadline) = read_deadline {
+                let left = deadline
+                    .saturating_duration_since(now)
+                    .max(Duration::from_millis(1));
+                timeout = Some(timeout.map_or(left, |t| t.min(left)));
+            }
+            if timeout.is_some() {
+                if let Err(e) = self.stream.set_read_timeout(timeout) {
+                    return Err(e.to_string());
+                }
+            }
+
+            let mut data = [0u8; 512];
+            match self.shared.transport.read(&self.stream, &mut data) {
+                Ok(0) => return Err("Connection closed by peer".to_string()),
+                Ok(n) => self
+                    .shared
+                    .read_buf
+                    .lock()
+                    .unwrap()
+                    .extend_from_slice(&data[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    if read_deadline.is_some_and(|d| Instant::now() >= d) {
+                        return Err("Read timed out".to_string());
+                    }
+                    return Ok(None);
+                }
+                Err(e) => {
+                    println!("Failed to read: {:?}", e);
+                    return Err("Read failed".to_string());
+                }
+            }
+        }
+    }
+
+    fn keepalive_tick(&mut self) -> Result<(), String> {
+        let action = self.shared.keepalive.lock().unwrap().poll(Instant::now());
+
+        match action {
+            KeepAliveAction::Nothing => Ok(()),
+            KeepAliveAction::Ping(payload) => {
+                self.shared
+                    .rtt
+                    .lock()
+                    .unwrap()
+                    .on_ping_sent(&payload, Instant::now());
+                self.write(&payload, FrameType::Ping)
+            }
+            KeepAliveAction::Idle => {
+                self.close(1001, "Idle timeout")?;
+                Err("Connection idle, closed with 1001".to_string())
+            }
+            KeepAliveAction::Dead => {
+                let _ = self.stream.shutdown(Shutdown::Both);
+                Err("Keepalive timeout, pongs missed, closed abnormally (1006)".to_string())
+            }
+        }
     }
 }
@@ -0,0 +1,32 @@
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::RootCertStore;
+
+/// Every certificate in `pem` in order, e.g. a chain with the leaf first. Fails when there is
+/// none.
+pub fn certs_from_pem(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, String> {
+    match CertificateDer::pem_slice_iter(pem).collect::<Result<Vec<_>, _>>() {
+        Ok(c) if !c.is_empty() => Ok(c),
+        Ok(_) => Err("No certificate found in PEM".to_string()),
+        Err(e) => Err(format!("Invalid certificate PEM: {}", e)),
+    }
+}
+
+/// The first private key in `pem`, PKCS#8, PKCS#1 or SEC1
+pub fn key_from_pem(pem: &[u8]) -> Result<PrivateKeyDer<'static>, String> {
+    match PrivateKeyDer::from_pem_slice(pem) {
+        Ok(k) => Ok(k),
+        Err(e) => Err(format!("Invalid key PEM: {}", e)),
+    }
+}
+
+/// Trusts the CA certificates in `ca_pem`, for verifying servers as well as clients
+pub fn roots_from_pem(ca_pem: &[u8]) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs_from_pem(ca_pem)? {
+        if let Err(e) = roots.add(cert) {
+            return Err(format!("Invalid CA certificate: {}", e));
+        }
+    }
+    Ok(roots)
+}
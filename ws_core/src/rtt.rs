@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Pings kept waiting for their pong, older ones are forgotten
+const MAX_IN_FLIGHT: usize = 16;
+
+/// Tracks the pings in flight and keeps a smoothed round-trip time, updated with 1/8 of every
+/// new sample like the TCP SRTT
+pub(crate) struct RttState {
+    in_flight: VecDeque<(Vec<u8>, Instant)>,
+    answered: Vec<(Vec<u8>, Duration)>,
+    srtt: Option<Duration>,
+    seq: u64,
+}
+
+impl RttState {
+    pub(crate) fn new() -> Self {
+        RttState {
+            in_flight: VecDeque::new(),
+            answered: vec![],
+            srtt: None,
+            seq: 0,
+        }
+    }
+
+    /// Payload for a ping sent through `WSStream::ping`, kept apart from the keepalive ones
+    pub(crate) fn next_payload(&mut self) -> Vec<u8> {
+        self.seq += 1;
+        let mut payload = b"rtt".to_vec();
+        payload.extend_from_slice(&self.seq.to_be_bytes());
+        payload
+    }
+
+    pub(crate) fn on_ping_sent(&mut self, payload: &[u8], now: Instant) {
+        if self.in_flight.len() == MAX_IN_FLIGHT {
+            self.in_flight.pop_front();
+        }
+        self.in_flight.push_back((payload.to_vec(), now));
+    }
+
+    /// Returns true when the pong matched a ping in flight
+    pub(crate) fn on_pong(&mut self, payload: &[u8], now: Instant) -> bool {
+        let idx = match self.in_flight.iter().position(|(p, _)| p == payload) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        let (payload, sent) = self.in_flight.remove(idx).unwrap();
+        let sample = now.duration_since(sent);
+        self.srtt = Some(match self.srtt {
+            Some(srtt) => (srtt * 7 + sample) / 8,
+            None => sample,
+        });
+
+        if payload.starts_with(b"rtt") {
+            self.answered.push((payload, sample));
+        }
+        true
+    }
+
+    pub(crate) fn take_answer(&mut self, payload: &[u8]) -> Option<Duration> {
+        let idx = self.answered.iter().position(|(p, _)| p == payload)?;
+        Some(self.answered.swap_remove(idx).1)
+    }
+
+    pub(crate) fn forget(&mut self, payload: &[u8]) {
+        self.in_flight.retain(|(p, _)| p != payload);
+    }
+
+    pub(crate) fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+}
@@ -0,0 +1,187 @@
+use crate::data_frame_tx::FrameType;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long closing a connection waits for the messages still queued to be written
+pub const CLOSE_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What happens to a message written while the send queue is full
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// The writer waits until the queue has room
+    Block,
+    /// Queued data messages are discarded, oldest first, until the new one fits
+    DropOldest,
+    /// The new message is discarded
+    DropNewest,
+    /// The connection is closed with the given code, usually 1008 or 1013
+    Close(u16),
+}
+
+/// Bounds of the per connection send queue, a message is always accepted into an empty queue
+/// even when it is larger than `max_bytes`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SendQueueConfig {
+    pub max_messages: usize,
+    pub max_bytes: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for SendQueueConfig {
+    fn default() -> Self {
+        SendQueueConfig {
+            max_messages: 1024,
+            max_bytes: 16 * 1024 * 1024,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SendQueueStats {
+    /// Messages waiting to be written
+    pub len: usize,
+    /// Payload bytes waiting to be written
+    pub bytes: usize,
+    /// Messages discarded by `DropOldest` or `DropNewest`, or still queued when the connection
+    /// was closed
+    pub dropped: u64,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum QueueError {
+    Closed,
+    Overflow(u16),
+}
+
+struct QueueState {
+    msgs: VecDeque<(FrameType, Vec<u8>)>,
+    stats: SendQueueStats,
+    closed: bool,
+    /// The writer popped a message and has not written it yet
+    writing: bool,
+}
+
+/// Data messages waiting for the writer thread of a connection. Control frames never go through
+/// it, `WSStream::write` hands them straight to the writer so queued data cannot hold them up.
+pub(crate) struct SendQueue {
+    config: SendQueueConfig,
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl SendQueue {
+    pub(crate) fn new(config: SendQueueConfig) -> Self {
+        SendQueue {
+            config,
+            state: Mutex::new(QueueState {
+                msgs: VecDeque::new(),
+                stats: SendQueueStats::default(),
+                closed: false,
+                writing: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn push(&self, f_type: FrameType, payload: Vec<u8>) -> Result<(), QueueError> {
+        let mut st = self.state.lock().unwrap();
+
+        loop {
+            if st.closed {
+                return Err(QueueError::Closed);
+            }
+
+            let fits = st.stats.len < self.config.max_messages
+                && st.stats.bytes + payload.len() <= self.config.max_bytes;
+            if st.msgs.is_empty() || fits {
+                break;
+            }
+
+            match self.config.overflow {
+                OverflowPolicy::Block => st = self.not_full.wait(st).unwrap(),
+                OverflowPolicy::DropOldest => {
+                    let (_, dropped) = st.msgs.pop_front().unwrap();
+                    st.stats.len -= 1;
+                    st.stats.bytes -= dropped.len();
+                    st.stats.dropped += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    st.stats.dropped += 1;
+                    return Ok(());
+                }
+                OverflowPolicy::Close(code) => return Err(QueueError::Overflow(code)),
+            }
+        }
+
+        st.stats.len += 1;
+        st.stats.bytes += payload.len();
+        st.msgs.push_back((f_type, payload));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Blocks until a message is queued, `None` once the queue is closed and drained
+    pub(crate) fn pop(&self) -> Option<(FrameType, Vec<u8>)> {
+        let mut st = self.state.lock().unwrap();
+        loop {
+            if let Some((f_type, payload)) = st.msgs.pop_front() {
+                st.stats.len -= 1;
+                st.stats.bytes -= payload.len();
+                st.writing = true;
+                self.not_full.notify_all();
+                return Some((f_type, payload));
+            }
+
+            if st.closed {
+                return None;
+            }
+            st = self.not_empty.wait(st).unwrap();
+        }
+    }
+
+    /// Called by the writer once the message it popped is written, or failed to be
+    pub(crate) fn written(&self) {
+        self.state.lock().unwrap().writing = false;
+        self.not_full.notify_all();
+    }
+
+    /// Stops accepting messages and waits up to `timeout` for the writer to write what is
+    /// queued. Whatever is left is discarded, counted as dropped and its number returned.
+    pub(crate) fn close_and_drain(&self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        let mut st = self.state.lock().unwrap();
+        st.closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+
+        while !st.msgs.is_empty() || st.writing {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            st = self.not_full.wait_timeout(st, deadline - now).unwrap().0;
+        }
+
+        let unsent = st.msgs.len();
+        st.msgs.clear();
+        st.stats.len = 0;
+        st.stats.bytes = 0;
+        st.stats.dropped += unsent as u64;
+        unsent
+    }
+
+    /// Stops accepting messages, the writer still drains what is queued
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    pub(crate) fn stats(&self) -> SendQueueStats {
+        self.state.lock().unwrap().stats
+    }
+}
@@ -82,5 +82,5 @@ fn pad(msg: &[u8]) -> Vec<u8> {
 }
 
 fn cls(x: u32, n: u8) -> u32 {
-    (x << n) | (x >> (32 - n))
+    x.rotate_left(n as u32)
 }
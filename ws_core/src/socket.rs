@@ -0,0 +1,107 @@
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Pending connections a listener queues unless told otherwise, the same as std
+pub const DEFAULT_BACKLOG: i32 = 128;
+
+/// Options of the TCP sockets a server listens on or a client connects with. Anything left
+/// unset keeps the system default.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SocketOptions {
+    /// `SO_REUSEADDR`, listeners always have it on unix like std's
+    pub reuse_address: bool,
+    /// `SO_REUSEPORT`, lets several processes listen on the same port. Unix only.
+    pub reuse_port: bool,
+    /// `TCP_NODELAY`, sends small frames right away instead of coalescing them
+    pub nodelay: bool,
+    /// `SO_KEEPALIVE` probing connections idle for this long. Unrelated to WebSocket pings,
+    /// see `KeepAlive` for those.
+    pub tcp_keepalive: Option<Duration>,
+    /// `SO_RCVBUF`
+    pub recv_buffer_size: Option<usize>,
+    /// `SO_SNDBUF`
+    pub send_buffer_size: Option<usize>,
+    /// `IPV6_V6ONLY` of IPv6 sockets, whether `[::]` leaves IPv4 connections out
+    pub only_v6: Option<bool>,
+}
+
+impl SocketOptions {
+    /// Binds `addr` and listens on it, connections accepted later still need `apply`
+    pub fn listen(&self, addr: SocketAddr, backlog: i32) -> io::Result<TcpListener> {
+        let socket = self.socket(addr)?;
+        if cfg!(unix) {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(backlog)?;
+        Ok(socket.into())
+    }
+
+    /// Connects to `addr`, within `timeout` if set
+    pub fn connect(&self, addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        let socket = self.socket(addr)?;
+        match timeout {
+            Some(t) => socket.connect_timeout(&addr.into(), t)?,
+            None => socket.connect(&addr.into())?,
+        }
+        Ok(socket.into())
+    }
+
+    /// Sets the options of a single connection on `stream`, as not every system has accepted
+    /// connections inherit them from the listener
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        self.apply_to(&SockRef::from(stream))
+    }
+
+    fn socket(&self, addr: SocketAddr) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if self.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        if self.reuse_port {
+            set_reuse_port(&socket)?;
+        }
+        if let (SocketAddr::V6(_), Some(only_v6)) = (addr, self.only_v6) {
+            socket.set_only_v6(only_v6)?;
+        }
+        self.apply_to(&socket)?;
+        Ok(socket)
+    }
+
+    fn apply_to(&self, socket: &Socket) -> io::Result<()> {
+        if self.nodelay {
+            socket.set_tcp_nodelay(true)?;
+        }
+        if let Some(time) = self.tcp_keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+)))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this system",
+    ))
+}
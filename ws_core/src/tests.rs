@@ -1,3 +1,12 @@
 mod base64_tests;
+#[cfg(test)]
+mod common;
 mod df_tests;
+mod http_tests;
+mod keepalive_tests;
+mod limits_tests;
+mod rtt_tests;
+mod send_queue_tests;
 mod sha1_tests;
+mod socket_tests;
+mod writer_tests;
@@ -0,0 +1,39 @@
+//! Handler and sockets shared by the tests running a `WSStream`
+
+use crate::data_frame_tx::Agent;
+use crate::WSHandler;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// Keeps the text messages it receives
+pub(crate) struct TestHandler {
+    pub(crate) agent: Agent,
+    pub(crate) text_msgs: Mutex<Vec<String>>,
+}
+
+impl WSHandler for TestHandler {
+    fn who(&self) -> Agent {
+        self.agent
+    }
+
+    fn handle_text_msg(&self, msg: String) {
+        self.text_msgs.lock().unwrap().push(msg);
+    }
+
+    fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+}
+
+pub(crate) fn handler(agent: Agent) -> Arc<TestHandler> {
+    Arc::new(TestHandler {
+        agent,
+        text_msgs: Mutex::new(vec![]),
+    })
+}
+
+/// Server and client end of a loopback connection
+pub(crate) fn socket_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (server, client)
+}
@@ -136,6 +136,32 @@ mod tests {
         assert_eq!(expected, d_frame);
     }
 
+    #[test]
+    fn test_server_df_parsed_by_client() {
+        let data = "Hello";
+        let d_frame =
+            Vec::from(DataFrame::build(data.as_bytes(), FrameType::Text, Agent::Server).unwrap());
+        assert_eq!(DFParser::frame_len(&d_frame), Ok(Some(7)));
+        assert_eq!(DFParser::frame_len(&d_frame[..6]), Ok(None));
+
+        let payload = DFParser::parse(&d_frame, Agent::Client).unwrap();
+        assert_eq!(payload.f_type, FrameType::Text);
+        assert_eq!(payload.data, data.as_bytes());
+
+        let pong = Vec::from(DataFrame::build(&[], FrameType::Pong, Agent::Server).unwrap());
+        let payload = DFParser::parse(&pong, Agent::Client).unwrap();
+        assert_eq!(payload.f_type, FrameType::Pong);
+        assert!(payload.data.is_empty());
+    }
+
+    #[test]
+    fn test_frame_len_overflow() {
+        let mut header = vec![0x82u8, 0x7F];
+        header.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(DFParser::frame_len(&header).is_err());
+        assert!(DFParser::parse(&header, Agent::Client).is_err());
+    }
+
     #[test]
     fn test_long_ping_df() {
         let data = "The quick brown fox jumps over the lazy dog. This pangram contains \
@@ -144,6 +170,7 @@ mod tests {
         assert!(DataFrame::build(data.as_bytes(), FrameType::Ping, Agent::Client).is_err());
     }
 
+    #[allow(dead_code)]
     struct TestWSHandler {
         control_msg: String,
         text_msg: String,
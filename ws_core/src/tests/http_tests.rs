@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use crate::http_utils::{parse_request, parse_response, read_head, HeadError, HeadLimits};
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const REQUEST: &[u8] = b"GET /chat?room=1 HTTP/1.1\r\n\
+        Host: example.com\r\n\
+        Connection: keep-alive, Upgrade\r\n\
+        cookie: a=1\r\n\
+        Cookie: b=2\r\n\
+        X-Empty:\r\n\
+        \r\n\
+        frame bytes";
+
+    #[test]
+    fn test_parse_request() {
+        let (head, len) = parse_request(REQUEST, &HeadLimits::default()).unwrap();
+
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.target, "/chat?room=1");
+        assert_eq!(head.version, "HTTP/1.1");
+        assert_eq!(&REQUEST[len..], b"frame bytes");
+
+        let headers = head.headers;
+        assert_eq!(headers.len(), 5);
+        assert_eq!(headers.get("HOST"), Some("example.com"));
+        assert_eq!(
+            headers.get_all("Cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+        assert_eq!(headers.tokens("connection"), ["keep-alive", "Upgrade"]);
+        assert!(headers.has_token("Connection", "upgrade"));
+        assert!(!headers.has_token("Connection", "close"));
+        assert_eq!(headers.get("x-empty"), Some(""));
+        assert_eq!(headers.get("missing"), None);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let res = b"HTTP/1.1 404 Not Found\r\nContent-Length: 2\r\n\r\nno";
+        let (head, len) = parse_response(res, &HeadLimits::default()).unwrap();
+
+        assert_eq!(head.status, 404);
+        assert_eq!(head.reason, "Not Found");
+        assert_eq!(&head.headers["content-length"], "2");
+        assert_eq!(&res[len..], b"no");
+
+        let (head, _) = parse_response(b"HTTP/1.1 200 \r\n\r\n", &HeadLimits::default()).unwrap();
+        assert_eq!(head.reason, "");
+    }
+
+    #[test]
+    fn test_incomplete_and_limits() {
+        let limits = HeadLimits {
+            max_size: 64,
+            max_headers: 2,
+        };
+
+        assert_eq!(
+            parse_request(b"GET / HTTP/1.1\r\nHost: a\r\n", &limits),
+            Err(HeadError::Incomplete)
+        );
+        assert_eq!(
+            parse_request(&[b'a'; 64], &limits),
+            Err(HeadError::TooLarge(64))
+        );
+        let long = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", "x".repeat(40));
+        assert_eq!(
+            parse_request(long.as_bytes(), &limits),
+            Err(HeadError::TooLarge(64))
+        );
+        assert_eq!(
+            parse_request(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n", &limits),
+            Err(HeadError::TooManyHeaders(2))
+        );
+    }
+
+    #[test]
+    fn test_malformed_heads() {
+        let limits = HeadLimits::default();
+        let parse = |head: &str| parse_request(head.as_bytes(), &limits).unwrap_err();
+
+        assert_eq!(parse("GET / HTTP/1.1\nHost: a\n\n"), HeadError::BareLf(1));
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost: a\n\r\n"),
+            HeadError::BareLf(2)
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost a\r\n\r\n"),
+            HeadError::InvalidHeader(2, "Missing colon".to_string())
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost : a\r\n\r\n"),
+            HeadError::InvalidHeader(2, "Invalid field name \"Host \"".to_string())
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nA: 1\r\n continued\r\n\r\n"),
+            HeadError::InvalidHeader(3, "Obsolete line folding".to_string())
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.0\r\n\r\n"),
+            HeadError::InvalidVersion("HTTP/1.0".to_string())
+        );
+        assert_eq!(
+            parse("GET /  HTTP/1.1\r\n\r\n"),
+            HeadError::InvalidStartLine("GET /  HTTP/1.1".to_string())
+        );
+        assert!(parse_response(b"HTTP/1.1 1O1 Switching\r\n\r\n", &limits).is_err());
+    }
+
+    #[test]
+    fn test_read_head() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            for part in REQUEST.chunks(10) {
+                stream.write_all(part).unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let socket = stream.try_clone().unwrap();
+        let (head, rest) = read_head(
+            &mut stream,
+            &socket,
+            &HeadLimits::default(),
+            None,
+            parse_request,
+        )
+        .unwrap();
+        assert_eq!(head.target, "/chat?room=1");
+        // whatever arrived with the end of the head
+        assert!(b"frame bytes".starts_with(&rest));
+
+        let deadline = Some(Instant::now() + Duration::from_millis(100));
+        assert_eq!(
+            read_head(
+                &mut stream,
+                &socket,
+                &HeadLimits::default(),
+                deadline,
+                parse_request
+            )
+            .unwrap_err(),
+            HeadError::TimedOut
+        );
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use crate::data_frame_tx::Agent;
+    use crate::keepalive::KeepAlive;
+    use crate::tests::common::{handler, socket_pair, TestHandler};
+    use crate::WSStream;
+    use std::io::Read;
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn server_stream(stream: TcpStream, keepalive: KeepAlive) -> WSStream<TestHandler> {
+        let mut ws_stream = WSStream::new(stream, handler(Agent::Server));
+        ws_stream.set_keepalive(Some(keepalive)).unwrap();
+        ws_stream
+    }
+
+    #[test]
+    fn test_unresponsive_peer_is_dropped() {
+        let (server, mut peer) = socket_pair();
+        let mut ws_stream = server_stream(
+            server,
+            KeepAlive {
+                interval: Duration::from_millis(30),
+                max_missed_pongs: 2,
+                idle_timeout: None,
+            },
+        );
+
+        let start = Instant::now();
+        let err = loop {
+            if let Err(e) = ws_stream.read() {
+                break e;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5));
+        };
+        assert!(err.contains("1006"));
+
+        let mut pings = vec![];
+        peer.read_to_end(&mut pings).unwrap();
+        assert_eq!(pings.first(), Some(&0x89));
+        assert_eq!(pings.len(), 2 * 10);
+    }
+
+    #[test]
+    fn test_responsive_peer_stays_connected() {
+        let (server, client) = socket_pair();
+        let mut ws_stream = server_stream(
+            server,
+            KeepAlive {
+                interval: Duration::from_millis(20),
+                max_missed_pongs: 2,
+                idle_timeout: None,
+            },
+        );
+
+        let mut client_stream = WSStream::new(client, handler(Agent::Client));
+        thread::spawn(move || while client_stream.read().is_ok() {});
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(300) {
+            ws_stream.read().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_idle_connection_is_closed() {
+        let (server, mut peer) = socket_pair();
+        let mut ws_stream = server_stream(
+            server,
+            KeepAlive {
+                interval: Duration::from_secs(10),
+                max_missed_pongs: 2,
+                idle_timeout: Some(Duration::from_millis(50)),
+            },
+        );
+
+        let err = ws_stream.read().unwrap_err();
+        assert!(err.contains("1001"));
+
+        let mut close = vec![];
+        peer.read_to_end(&mut close).unwrap();
+        assert_eq!(&close[..4], &[0x88, 14, 0x03, 0xE9]);
+        assert_eq!(&close[4..], "Idle timeout".as_bytes());
+    }
+}
@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+    use crate::data_frame_tx::{Agent, DataFrame, FrameType};
+    use crate::tests::common::{handler, socket_pair, TestHandler};
+    use crate::{WSStream, DEFAULT_MAX_MESSAGE_SIZE};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::{Duration, Instant};
+
+    fn server_stream() -> (WSStream<TestHandler>, TcpStream) {
+        let (server, client) = socket_pair();
+        (WSStream::new(server, handler(Agent::Server)), client)
+    }
+
+    fn send(client: &mut TcpStream, data: &[u8], f_type: FrameType, fin: bool) {
+        let df = DataFrame::build_fragment(data, f_type, fin, Agent::Client).unwrap();
+        client.write_all(&Vec::from(df)).unwrap();
+    }
+
+    fn close_code(client: &mut TcpStream) -> u16 {
+        let mut buf = [0u8; 64];
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let n = client.read(&mut buf).unwrap();
+        assert!(n >= 4);
+        assert_eq!(buf[0], 0x88);
+        u16::from_be_bytes([buf[2], buf[3]])
+    }
+
+    #[test]
+    fn test_max_frame_size() {
+        let (mut ws_stream, mut client) = server_stream();
+        ws_stream.set_max_frame_size(16);
+
+        send(&mut client, &[b'a'; 100], FrameType::Text, true);
+
+        let e = ws_stream.read().unwrap_err();
+        assert!(e.contains("1009"));
+        assert_eq!(close_code(&mut client), 1009);
+        assert!(ws_stream.handler.text_msgs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_default_max_frame_size() {
+        let (mut ws_stream, mut client) = server_stream();
+
+        // announces a terabyte and sends none of it
+        let mut header = vec![0x82u8, 0xFF];
+        header.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        header.extend_from_slice(&[0u8; 4]);
+        client.write_all(&header).unwrap();
+
+        let e = ws_stream.read().unwrap_err();
+        assert!(e.contains("1009"));
+        assert_eq!(close_code(&mut client), 1009);
+    }
+
+    #[test]
+    fn test_max_message_size() {
+        let (mut ws_stream, mut client) = server_stream();
+        ws_stream.set_max_frame_size(16);
+        ws_stream.set_max_message_size(20);
+
+        send(&mut client, b"0123456789", FrameType::Text, true);
+        ws_stream.read().unwrap();
+        assert_eq!(
+            *ws_stream.handler.text_msgs.lock().unwrap(),
+            vec!["0123456789".to_string()]
+        );
+
+        // every fragment is under the frame limit, the message is not
+        send(&mut client, b"0123456789", FrameType::Text, false);
+        send(&mut client, b"0123456789", FrameType::Continuation, false);
+        send(&mut client, b"0123456789", FrameType::Continuation, true);
+
+        ws_stream.read().unwrap();
+        ws_stream.read().unwrap();
+        let e = ws_stream.read().unwrap_err();
+        assert!(e.contains("1009"));
+        assert_eq!(close_code(&mut client), 1009);
+
+        // 0 is the default limit, not none. The fragments received so far are put in place
+        // rather than sent.
+        let (mut ws_stream, mut client) = server_stream();
+        ws_stream.set_max_message_size(0);
+        *ws_stream.shared.partial.lock().unwrap() =
+            Some((FrameType::Text, vec![b'a'; DEFAULT_MAX_MESSAGE_SIZE]));
+        send(&mut client, b"a", FrameType::Continuation, true);
+
+        let e = ws_stream.read().unwrap_err();
+        assert!(e.contains("1009"));
+        assert_eq!(close_code(&mut client), 1009);
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let (mut ws_stream, _client) = server_stream();
+        ws_stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        let started = Instant::now();
+        assert_eq!(ws_stream.read().unwrap_err(), "Read timed out");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_close_replies() {
+        let (mut ws_stream, mut client) = server_stream();
+        send(&mut client, &1001u16.to_be_bytes(), FrameType::Close, true);
+        ws_stream.read().unwrap();
+        assert_eq!(ws_stream.peer_close_code(), Some(1001));
+
+        // the status code alone, nothing else
+        let mut buf = [0u8; 64];
+        let n = client.read(&mut buf).unwrap();
+        assert_eq!(buf[..n], [0x88, 0x02, 0x03, 0xE9]);
+
+        let (mut ws_stream, mut client) = server_stream();
+        send(&mut client, &[0xC3, 0x28], FrameType::Text, true);
+        assert!(ws_stream.read().is_err());
+        assert_eq!(close_code(&mut client), 1007);
+
+        let (mut ws_stream, mut client) = server_stream();
+        send(&mut client, b"rest", FrameType::Continuation, true);
+        assert!(ws_stream.read().is_err());
+        assert_eq!(close_code(&mut client), 1002);
+    }
+}
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod tests {
+    use crate::data_frame_tx::Agent;
+    use crate::tests::common::{handler, socket_pair, TestHandler};
+    use crate::WSStream;
+    use std::thread;
+    use std::time::Duration;
+
+    fn stream_pair() -> (WSStream<TestHandler>, WSStream<TestHandler>) {
+        let (server, client) = socket_pair();
+        (
+            WSStream::new(server, handler(Agent::Server)),
+            WSStream::new(client, handler(Agent::Client)),
+        )
+    }
+
+    #[test]
+    fn test_ping_measures_rtt() {
+        let (mut server, mut client) = stream_pair();
+        thread::spawn(move || while client.read().is_ok() {});
+
+        let mut reader = server.clone();
+        thread::spawn(move || while reader.read().is_ok() {});
+
+        assert_eq!(server.rtt(), None);
+        let first = server.ping(Duration::from_secs(5)).unwrap();
+        assert_eq!(server.rtt(), Some(first));
+
+        let second = server.ping(Duration::from_secs(5)).unwrap();
+        assert_eq!(server.rtt(), Some((first * 7 + second) / 8));
+    }
+
+    #[test]
+    fn test_ping_times_out_without_pong() {
+        let (mut server, _client) = stream_pair();
+
+        let mut reader = server.clone();
+        thread::spawn(move || while reader.read().is_ok() {});
+
+        assert!(server.ping(Duration::from_millis(50)).is_err());
+        assert_eq!(server.rtt(), None);
+    }
+}
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod tests {
+    use crate::data_frame_rx::DFParser;
+    use crate::data_frame_tx::{Agent, FrameType};
+    use crate::send_queue::{OverflowPolicy, QueueError, SendQueue, SendQueueConfig};
+    use crate::tests::common::{handler, socket_pair};
+    use crate::WSStream;
+    use std::io::Read;
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn queue(overflow: OverflowPolicy) -> SendQueue {
+        SendQueue::new(SendQueueConfig {
+            max_messages: 2,
+            max_bytes: 1024,
+            overflow,
+        })
+    }
+
+    fn push_text(queue: &SendQueue, msg: &str) -> Result<(), QueueError> {
+        queue.push(FrameType::Text, msg.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let queue = queue(OverflowPolicy::DropOldest);
+        push_text(&queue, "one").unwrap();
+        push_text(&queue, "two").unwrap();
+        push_text(&queue, "three").unwrap();
+
+        let stats = queue.stats();
+        assert_eq!((stats.len, stats.bytes, stats.dropped), (2, 8, 1));
+        assert_eq!(queue.pop().unwrap().1, b"two");
+        assert_eq!(queue.pop().unwrap().1, b"three");
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let queue = queue(OverflowPolicy::DropNewest);
+        push_text(&queue, "one").unwrap();
+        push_text(&queue, "two").unwrap();
+        push_text(&queue, "three").unwrap();
+
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(queue.pop().unwrap().1, b"one");
+        assert_eq!(queue.pop().unwrap().1, b"two");
+    }
+
+    #[test]
+    fn test_close_on_overflow() {
+        let queue = queue(OverflowPolicy::Close(1013));
+        push_text(&queue, "one").unwrap();
+        push_text(&queue, "two").unwrap();
+        assert_eq!(push_text(&queue, "three"), Err(QueueError::Overflow(1013)));
+        assert_eq!(queue.stats().len, 2);
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let queue = queue(OverflowPolicy::DropNewest);
+        queue.push(FrameType::Binary, vec![0; 1000]).unwrap();
+        queue.push(FrameType::Binary, vec![0; 100]).unwrap();
+        assert_eq!(queue.stats().dropped, 1);
+
+        // an empty queue takes a message of any size
+        queue.pop().unwrap();
+        queue.push(FrameType::Binary, vec![0; 4096]).unwrap();
+        assert_eq!(queue.stats().bytes, 4096);
+    }
+
+    #[test]
+    fn test_block_until_popped() {
+        let queue = Arc::new(queue(OverflowPolicy::Block));
+        push_text(&queue, "one").unwrap();
+        push_text(&queue, "two").unwrap();
+
+        let pusher = queue.clone();
+        let (tx, rx) = channel();
+        thread::spawn(move || tx.send(push_text(&pusher, "three")).unwrap());
+
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(50)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert_eq!(queue.stats().len, 2);
+        assert_eq!(queue.pop().unwrap().1, b"one");
+
+        rx.recv().unwrap().unwrap();
+        assert_eq!(queue.stats().len, 2);
+
+        queue.close();
+        assert!(push_text(&queue, "four").is_err());
+        assert_eq!(queue.pop().unwrap().1, b"two");
+        assert_eq!(queue.pop().unwrap().1, b"three");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_slow_peer_is_bounded() {
+        let (server, _peer) = socket_pair();
+
+        let mut ws_stream = WSStream::new(server, handler(Agent::Server));
+        ws_stream
+            .set_send_queue(SendQueueConfig {
+                max_messages: 4,
+                max_bytes: 1024 * 1024,
+                overflow: OverflowPolicy::DropNewest,
+            })
+            .unwrap();
+
+        let msg = vec![0u8; 64 * 1024];
+        for _ in 0..1000 {
+            ws_stream.write(&msg, FrameType::Binary).unwrap();
+        }
+
+        let stats = ws_stream.send_queue_stats().unwrap();
+        assert!(stats.len <= 4);
+        assert!(stats.bytes <= 4 * msg.len());
+        assert!(stats.dropped > 0);
+    }
+
+    #[test]
+    fn test_close_and_drain() {
+        let queue = queue(OverflowPolicy::Block);
+        push_text(&queue, "one").unwrap();
+        push_text(&queue, "two").unwrap();
+
+        // nothing writes them
+        assert_eq!(queue.close_and_drain(Duration::from_millis(20)), 2);
+        let stats = queue.stats();
+        assert_eq!((stats.len, stats.bytes, stats.dropped), (0, 0, 2));
+        assert_eq!(push_text(&queue, "three"), Err(QueueError::Closed));
+    }
+
+    #[test]
+    fn test_close_writes_queued_messages_first() {
+        let (server, mut peer) = socket_pair();
+        let mut ws_stream = WSStream::new(server, handler(Agent::Server));
+        ws_stream
+            .set_send_queue(SendQueueConfig::default())
+            .unwrap();
+
+        for i in 0..50 {
+            ws_stream
+                .write(format!("msg {}", i).as_bytes(), FrameType::Text)
+                .unwrap();
+        }
+        ws_stream.close(1000, "done").unwrap();
+
+        let mut buf = vec![];
+        peer.read_to_end(&mut buf).unwrap();
+        let mut frames = vec![];
+        while let Ok(Some(len)) = DFParser::frame_len(&buf) {
+            let frame: Vec<u8> = buf.drain(..len).collect();
+            frames.push(DFParser::parse(&frame, Agent::Client).unwrap());
+        }
+
+        assert_eq!(frames.len(), 51);
+        assert_eq!(frames[49].data, b"msg 49");
+        assert_eq!(frames[50].f_type, FrameType::Close);
+        assert_eq!(ws_stream.send_queue_stats().unwrap().dropped, 0);
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use crate::socket::{SocketOptions, DEFAULT_BACKLOG};
+    use socket2::SockRef;
+    use std::net::{Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+    use std::time::Duration;
+
+    fn options() -> SocketOptions {
+        SocketOptions {
+            nodelay: true,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            recv_buffer_size: Some(64 * 1024),
+            send_buffer_size: Some(64 * 1024),
+            ..Default::default()
+        }
+    }
+
+    fn check(stream: &TcpStream) {
+        let socket = SockRef::from(stream);
+        assert!(socket.tcp_nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        // linux doubles the sizes asked for
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[test]
+    fn test_listen_and_connect() {
+        let options = options();
+        let listener = options
+            .listen("127.0.0.1:0".parse().unwrap(), DEFAULT_BACKLOG)
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = options.connect(addr, Some(Duration::from_secs(1))).unwrap();
+        check(&client);
+
+        let (server, _) = listener.accept().unwrap();
+        options.apply(&server).unwrap();
+        check(&server);
+        assert_eq!(server.peer_addr().unwrap(), client.local_addr().unwrap());
+    }
+
+    #[test]
+    fn test_defaults_left_alone() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = SocketOptions::default()
+            .connect(listener.local_addr().unwrap(), None)
+            .unwrap();
+
+        assert!(!client.nodelay().unwrap());
+        assert!(!SockRef::from(&client).keepalive().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_reuse_port() {
+        let options = SocketOptions {
+            reuse_port: true,
+            ..Default::default()
+        };
+        let first = options
+            .listen("127.0.0.1:0".parse().unwrap(), DEFAULT_BACKLOG)
+            .unwrap();
+        let addr = first.local_addr().unwrap();
+
+        assert!(options.listen(addr, DEFAULT_BACKLOG).is_ok());
+        assert!(SocketOptions::default()
+            .listen(addr, DEFAULT_BACKLOG)
+            .is_err());
+    }
+
+    #[test]
+    fn test_only_v6() {
+        let any = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0);
+        let listener = match (SocketOptions {
+            only_v6: Some(true),
+            ..Default::default()
+        })
+        .listen(any, DEFAULT_BACKLOG)
+        {
+            Ok(l) => l,
+            // no IPv6 on this host
+            Err(_) => return,
+        };
+        assert!(SockRef::from(&listener).only_v6().unwrap());
+
+        // IPv4 connections are left to another listener
+        let port = listener.local_addr().unwrap().port();
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+}
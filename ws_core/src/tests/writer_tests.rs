@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use crate::data_frame_rx::DFParser;
+    use crate::data_frame_tx::{Agent, DataFrame, FrameType};
+    use crate::tests::common::{handler, socket_pair};
+    use crate::WSStream;
+    use std::io::Read;
+    use std::thread;
+
+    #[test]
+    fn test_fragment_df() {
+        let first = Vec::from(
+            DataFrame::build_fragment("Hel".as_bytes(), FrameType::Text, false, Agent::Server)
+                .unwrap(),
+        );
+        assert_eq!(first, vec![0x01, 0x03, 0x48, 0x65, 0x6c]);
+
+        let last = Vec::from(
+            DataFrame::build_fragment(
+                "lo".as_bytes(),
+                FrameType::Continuation,
+                true,
+                Agent::Server,
+            )
+            .unwrap(),
+        );
+        assert_eq!(last, vec![0x80, 0x02, 0x6c, 0x6f]);
+
+        let payload = DFParser::parse(&first, Agent::Client).unwrap();
+        assert!(!payload.fin);
+        assert_eq!(payload.f_type, FrameType::Text);
+        assert!(DFParser::parse(&last, Agent::Client).unwrap().fin);
+
+        assert!(DataFrame::build_fragment(&[], FrameType::Ping, false, Agent::Server).is_err());
+        assert!(DFParser::parse(&[0x09, 0x00], Agent::Client).is_err());
+    }
+
+    #[test]
+    fn test_fragmented_message_is_reassembled() {
+        let (server, client) = socket_pair();
+        let mut server = WSStream::new(server, handler(Agent::Server));
+        let client_handler = handler(Agent::Client);
+        let mut client = WSStream::new(client, client_handler.clone());
+
+        let msg = "The quick brown fox jumps over the lazy dog. ".repeat(10);
+        server.set_fragment_size(16);
+        server.write(msg.as_bytes(), FrameType::Text).unwrap();
+        server.write("after".as_bytes(), FrameType::Text).unwrap();
+
+        while client_handler.text_msgs.lock().unwrap().len() < 2 {
+            client.read().unwrap();
+        }
+        assert_eq!(
+            *client_handler.text_msgs.lock().unwrap(),
+            vec![msg, "after".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_control_frame_between_fragments() {
+        let (server, mut peer) = socket_pair();
+        let mut server = WSStream::new(server, handler(Agent::Server));
+
+        let msg = vec![7u8; 32 * 1024 * 1024];
+        let msg_len = msg.len();
+        let mut data_writer = server.clone();
+        let handle = thread::spawn(move || data_writer.write(&msg, FrameType::Binary));
+
+        let mut buf = vec![];
+        let mut frames = vec![];
+        let mut received = 0;
+        let mut pinged = false;
+        while received < msg_len || !frames.contains(&FrameType::Ping) {
+            // the first fragments arrived and the peer is not reading more, so the data writer
+            // is stuck in the middle of the message
+            if !pinged && !frames.is_empty() {
+                server.write("ping".as_bytes(), FrameType::Ping).unwrap();
+                pinged = true;
+            }
+
+            let mut data = [0u8; 64 * 1024];
+            let n = peer.read(&mut data).unwrap();
+            buf.extend_from_slice(&data[..n]);
+
+            while let Ok(Some(len)) = DFParser::frame_len(&buf) {
+                let frame: Vec<u8> = buf.drain(..len).collect();
+                let payload = DFParser::parse(&frame, Agent::Client).unwrap();
+                if payload.f_type != FrameType::Ping {
+                    received += payload.data.len();
+                }
+                frames.push(payload.f_type);
+            }
+        }
+        handle.join().unwrap().unwrap();
+
+        let ping_idx = frames.iter().position(|f| *f == FrameType::Ping).unwrap();
+        assert_eq!(frames[0], FrameType::Binary);
+        assert!(ping_idx > 0 && ping_idx < frames.len() - 1);
+        assert_eq!(frames[ping_idx + 1], FrameType::Continuation);
+    }
+}
@@ -0,0 +1,118 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+#[cfg(feature = "rustls")]
+use std::sync::Mutex;
+
+/// How the bytes of a `WSStream` travel over its socket, in the clear or through a TLS session.
+/// The session is shared by the clones of the stream, reading the socket happens outside the
+/// session lock so a reader blocked on the socket never holds up the writers.
+pub(crate) struct Transport {
+    #[cfg(feature = "rustls")]
+    tls: Option<Mutex<rustls::Connection>>,
+}
+
+impl Transport {
+    pub(crate) fn plain() -> Self {
+        Transport {
+            #[cfg(feature = "rustls")]
+            tls: None,
+        }
+    }
+
+    /// `conn` has to be done with its handshake, plaintext it already decrypted is read first
+    #[cfg(feature = "rustls")]
+    pub(crate) fn tls(conn: rustls::Connection) -> Self {
+        Transport {
+            tls: Some(Mutex::new(conn)),
+        }
+    }
+
+    pub(crate) fn read(&self, stream: &TcpStream, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "rustls")]
+        if let Some(tls) = &self.tls {
+            return read_tls(tls, stream, buf);
+        }
+
+        (&*stream).read(buf)
+    }
+
+    pub(crate) fn write_all(&self, stream: &TcpStream, buf: &[u8]) -> io::Result<()> {
+        #[cfg(feature = "rustls")]
+        if let Some(tls) = &self.tls {
+            return write_tls(tls, stream, buf);
+        }
+
+        (&*stream).write_all(buf)
+    }
+
+    /// Lets the peer know no more data follows before the socket is shut down
+    #[cfg_attr(not(feature = "rustls"), allow(unused_variables))]
+    pub(crate) fn close(&self, stream: &TcpStream) {
+        #[cfg(feature = "rustls")]
+        if let Some(tls) = &self.tls {
+            let mut conn = tls.lock().unwrap();
+            conn.send_close_notify();
+            while conn.wants_write() {
+                if conn.write_tls(&mut &*stream).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+fn read_tls(
+    tls: &Mutex<rustls::Connection>,
+    stream: &TcpStream,
+    buf: &mut [u8],
+) -> io::Result<usize> {
+    loop {
+        match tls.lock().unwrap().reader().read(buf) {
+            Ok(n) => return Ok(n),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => return Err(e),
+        }
+
+        let mut raw = [0u8; 4096];
+        let n = (&*stream).read(&mut raw)?;
+        if n == 0 {
+            return Ok(0);
+        }
+
+        let mut conn = tls.lock().unwrap();
+        let mut rd = &raw[..n];
+        while !rd.is_empty() {
+            conn.read_tls(&mut rd)?;
+            if let Err(e) = conn.process_new_packets() {
+                let _ = conn.write_tls(&mut &*stream);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+
+        while conn.wants_write() {
+            conn.write_tls(&mut &*stream)?;
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+fn write_tls(tls: &Mutex<rustls::Connection>, stream: &TcpStream, buf: &[u8]) -> io::Result<()> {
+    let mut conn = tls.lock().unwrap();
+    let mut rest = buf;
+
+    // the session only takes as much plaintext as fits its send buffer
+    loop {
+        let n = conn.writer().write(rest)?;
+        rest = &rest[n..];
+
+        while conn.wants_write() {
+            conn.write_tls(&mut &*stream)?;
+        }
+
+        if rest.is_empty() {
+            return Ok(());
+        }
+    }
+}
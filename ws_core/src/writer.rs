@@ -0,0 +1,146 @@
+use crate::data_frame_tx::{Agent, DataFrame, FrameType};
+use crate::transport::Transport;
+use std::collections::VecDeque;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Data messages larger than this are split into fragments unless configured otherwise
+pub(crate) const DEFAULT_FRAGMENT_SIZE: usize = 64 * 1024;
+
+/// Serialises the frames written by every clone of a stream. A data message holds
+/// `message_lock` for all of its fragments while `frame_lock` is only held per frame, so control
+/// frames get onto the wire between two fragments instead of waiting for the whole message.
+pub(crate) struct FrameWriter {
+    agent: Agent,
+    transport: Arc<Transport>,
+    message_lock: Mutex<()>,
+    frame_lock: Mutex<()>,
+    control_out: Mutex<VecDeque<Vec<u8>>>,
+    fragment_size: AtomicUsize,
+    closed: AtomicBool,
+}
+
+impl FrameWriter {
+    pub(crate) fn new(agent: Agent, transport: Arc<Transport>) -> Self {
+        FrameWriter {
+            agent,
+            transport,
+            message_lock: Mutex::new(()),
+            frame_lock: Mutex::new(()),
+            control_out: Mutex::new(VecDeque::new()),
+            fragment_size: AtomicUsize::new(DEFAULT_FRAGMENT_SIZE),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// 0 disables fragmentation
+    pub(crate) fn set_fragment_size(&self, size: usize) {
+        self.fragment_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Writes a text or binary message, fragmented when it is larger than the fragment size
+    pub(crate) fn write_message(
+        &self,
+        stream: &TcpStream,
+        data: &[u8],
+        f_type: FrameType,
+    ) -> Result<(), String> {
+        let _message = self.message_lock.lock().unwrap();
+
+        let size = match self.fragment_size.load(Ordering::Relaxed) {
+            0 => data.len().max(1),
+            s => s,
+        };
+
+        let mut chunks = data.chunks(size).peekable();
+        let mut frame_type = f_type;
+        if chunks.peek().is_none() {
+            return self.write_frame(stream, &[], f_type, true);
+        }
+
+        while let Some(chunk) = chunks.next() {
+            self.write_frame(stream, chunk, frame_type, chunks.peek().is_none())?;
+            frame_type = FrameType::Continuation;
+        }
+        Ok(())
+    }
+
+    /// Writes a control frame ahead of any fragment still to be written. Pings and pongs are left
+    /// to the writer currently on the stream when there is one, in which case this returns once
+    /// they are queued for it and a failure to write them is that writer's. A close frame waits
+    /// for the current frame to finish, is written before returning and stops the data messages
+    /// still being written.
+    pub(crate) fn write_control(
+        &self,
+        stream: &TcpStream,
+        data: &[u8],
+        f_type: FrameType,
+    ) -> Result<(), String> {
+        let df = Vec::from(DataFrame::build(data, f_type, self.agent)?);
+
+        if f_type == FrameType::Close {
+            let _frame = self.frame_lock.lock().unwrap();
+            self.drain_control(stream)?;
+            self.closed.store(true, Ordering::SeqCst);
+            return match self.transport.write_all(stream, &df) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            };
+        }
+
+        self.control_out.lock().unwrap().push_back(df);
+        self.flush_control(stream)
+    }
+
+    fn write_frame(
+        &self,
+        stream: &TcpStream,
+        chunk: &[u8],
+        f_type: FrameType,
+        fin: bool,
+    ) -> Result<(), String> {
+        let df = Vec::from(DataFrame::build_fragment(chunk, f_type, fin, self.agent)?);
+
+        {
+            let _frame = self.frame_lock.lock().unwrap();
+            self.drain_control(stream)?;
+            if self.closed.load(Ordering::SeqCst) {
+                return Err("Connection is closing".to_string());
+            }
+            if let Err(e) = self.transport.write_all(stream, &df) {
+                return Err(e.to_string());
+            }
+        }
+
+        self.flush_control(stream)
+    }
+
+    /// Writes the queued control frames unless another writer holds the stream, that writer
+    /// picks them up before its next frame or right after releasing it
+    fn flush_control(&self, stream: &TcpStream) -> Result<(), String> {
+        loop {
+            match self.frame_lock.try_lock() {
+                Ok(_frame) => self.drain_control(stream)?,
+                Err(_) => return Ok(()),
+            }
+
+            if self.control_out.lock().unwrap().is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Must be called with `frame_lock` held
+    fn drain_control(&self, stream: &TcpStream) -> Result<(), String> {
+        loop {
+            let df = match self.control_out.lock().unwrap().pop_front() {
+                Some(df) => df,
+                None => return Ok(()),
+            };
+            if let Err(e) = self.transport.write_all(stream, &df) {
+                return Err(e.to_string());
+            }
+        }
+    }
+}
@@ -21,7 +21,7 @@ impl WSHandler for ClientHandler {
 }
 
 pub fn client() {
-    let mut client = WSClient::connect("http://127.0.0.1:8080", ClientHandler {}).unwrap();
+    let mut client = WSClient::connect("ws://127.0.0.1:8080", ClientHandler {}).unwrap();
 
     let (tx, rx): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = channel();
 
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use crate::errors::HTTPError;
+use crate::server::WSUpgrade;
+use ws_core::conn_info::{AuthInfo, ConnectionInfo};
+
+/// Runs once the upgrade request is read and before the 101 is written, with the request and
+/// what is known about the peer. An `HTTPError` is sent back as the response, e.g.
+/// `get_http_error(HTTPStatus::Unauthorised, "Invalid token")` or
+/// `get_http_error(HTTPStatus::Other(302), "").with_header("Location", "/login")`.
+pub type AuthHook =
+    Arc<dyn Fn(&WSUpgrade, &ConnectionInfo) -> Result<Accept, HTTPError> + Send + Sync>;
+
+/// An authorised upgrade, `auth` ends up in `ConnectionInfo::auth` and `headers` in the 101
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Accept {
+    pub auth: Option<AuthInfo>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Accept {
+    pub fn new() -> Self {
+        Accept::default()
+    }
+
+    pub fn auth(mut self, auth: AuthInfo) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Adds a header to the 101 response, like `Set-Cookie`
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use crate::auth::Accept;
+    use crate::errors::{get_http_error, HTTPError, HTTPStatus};
+    use crate::server::{WSServerListener, WSUpgrade};
+    use crate::test_common::{server, upgrade_request};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::thread;
+    use ws_core::conn_info::{AuthInfo, ConnectionInfo};
+
+    fn request(extra: &str) -> String {
+        upgrade_request("/chat?room=42", extra)
+    }
+
+    /// Sends `req` and reads until the server closes or the 101 head is complete
+    fn send(addr: SocketAddr, req: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(req.as_bytes()).unwrap();
+
+        let mut res = vec![];
+        let mut buf = [0u8; 512];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => res.extend_from_slice(&buf[..n]),
+            }
+            if res.starts_with(b"HTTP/1.1 101") {
+                break;
+            }
+        }
+        String::from_utf8(res).unwrap()
+    }
+
+    fn authorize(req: &WSUpgrade, info: &ConnectionInfo) -> Result<Accept, HTTPError> {
+        assert_eq!(req.method(), "GET");
+        assert_eq!(req.path(), "/chat");
+        assert_eq!(info.query_param("room"), Some("42"));
+        assert!(info.peer_addr.unwrap().ip().is_loopback());
+
+        match (req.header("Authorization"), req.cookie("session")) {
+            (Some("Bearer good"), Some(session)) => Ok(Accept::new()
+                .auth(AuthInfo::new("alice").claim("session", &session))
+                .header("Set-Cookie", "seen=1; HttpOnly")),
+            (Some(_), _) => Err(get_http_error(HTTPStatus::Forbidden, "Not yours")),
+            (None, _) => Err(get_http_error(HTTPStatus::Unauthorised, "Token required")
+                .with_header("WWW-Authenticate", "Bearer")),
+        }
+    }
+
+    #[test]
+    fn test_cookies() {
+        let mut ws_upgrade = WSUpgrade::new();
+        ws_upgrade
+            .parse_handshake(request("Cookie: session=abc; theme=\"dark\"; flag\r\n").into_bytes())
+            .unwrap();
+
+        assert_eq!(
+            ws_upgrade.cookies(),
+            [
+                ("session".to_string(), "abc".to_string()),
+                ("theme".to_string(), "dark".to_string()),
+            ]
+        );
+        assert_eq!(ws_upgrade.cookie("theme"), Some("dark".to_string()));
+        assert_eq!(ws_upgrade.cookie("other"), None);
+        assert_eq!(ws_upgrade.header("HOST"), Some("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_auth_hook() {
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_auth(authorize);
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        let res = send(addr, &request(""));
+        assert!(res.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(res.contains("\r\nWWW-Authenticate: Bearer\r\n"));
+        assert!(res.ends_with("\r\n\r\nToken required"));
+
+        let res = send(addr, &request("Authorization: Bearer bad\r\n"));
+        assert!(res.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(res.ends_with("\r\n\r\nNot yours"));
+
+        // rejections leave the listener serving the next client
+
+        let res = send(
+            addr,
+            &request("Authorization: Bearer good\r\nCookie: session=abc\r\n"),
+        );
+        assert!(res.starts_with("HTTP/1.1 101 Switching Protocols"));
+        assert!(res.contains("\nSet-Cookie: seen=1; HttpOnly"));
+
+        let auth = server.join().unwrap().auth.unwrap();
+        assert_eq!(auth.user, "alice");
+        assert_eq!(auth.get("session"), Some("abc"));
+    }
+}
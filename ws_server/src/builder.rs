@@ -0,0 +1,85 @@
+use std::net::{SocketAddr, TcpListener};
+
+use crate::router::Router;
+use crate::server::WSServerListener;
+use ws_core::socket::{SocketOptions, DEFAULT_BACKLOG};
+use ws_core::WSHandler;
+
+/// Binds a listener on any addresses with socket options, e.g.
+/// `WSServerBuilder::new().bind("0.0.0.0:9000".parse().unwrap()).build(handler)`. The upgrade
+/// itself is configured on the `WSServerListener` built.
+#[derive(Clone, Debug, Default)]
+pub struct WSServerBuilder {
+    addrs: Vec<SocketAddr>,
+    backlog: Option<i32>,
+    socket: SocketOptions,
+}
+
+impl WSServerBuilder {
+    pub fn new() -> Self {
+        WSServerBuilder::default()
+    }
+
+    /// Listens on `addr`, once per call, e.g. on both `0.0.0.0:9000` and `[::]:9000`. When
+    /// IPv4 addresses are bound too IPv6 sockets are v6 only, unless `SocketOptions::only_v6`
+    /// says otherwise.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.addrs.push(addr);
+        self
+    }
+
+    /// Listens on each of `addrs`, see `bind`
+    pub fn bind_all<I>(mut self, addrs: I) -> Self
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        self.addrs.extend(addrs);
+        self
+    }
+
+    /// Connections waiting to be accepted before new ones are refused, 128 by default
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = Some(backlog);
+        self
+    }
+
+    /// Set on the listening sockets and on every connection accepted
+    pub fn socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket = options;
+        self
+    }
+
+    /// Serves `handler` on every path
+    pub fn build<H>(self, handler: H) -> Result<WSServerListener<H>, String>
+    where
+        H: WSHandler + Send + Sync + 'static,
+    {
+        self.build_with_router(Router::new().fallback(handler))
+    }
+
+    /// Picks the handler of each connection by its path, see `Router`
+    pub fn build_with_router<H>(self, router: Router<H>) -> Result<WSServerListener<H>, String>
+    where
+        H: WSHandler + Send + Sync + 'static,
+    {
+        if self.addrs.is_empty() {
+            return Err("No address to bind".to_string());
+        }
+
+        let mut v6 = self.socket;
+        if v6.only_v6.is_none() && self.addrs.iter().any(|a| a.is_ipv4()) {
+            v6.only_v6 = Some(true);
+        }
+
+        let mut listeners: Vec<TcpListener> = vec![];
+        for addr in &self.addrs {
+            let options = if addr.is_ipv6() { &v6 } else { &self.socket };
+            match options.listen(*addr, self.backlog.unwrap_or(DEFAULT_BACKLOG)) {
+                Ok(l) => listeners.push(l),
+                Err(e) => return Err(format!("Failed to bind {}: {}", addr, e)),
+            }
+        }
+
+        WSServerListener::from_listeners(listeners, self.socket, router)
+    }
+}
@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use crate::builder::WSServerBuilder;
+    use crate::test_common::{client, server};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+    use ws_client::builder::WSClientBuilder;
+    use ws_client::client::WSClient;
+    use ws_core::socket::SocketOptions;
+
+    #[test]
+    fn test_bind_ipv4_and_ipv6() {
+        if TcpListener::bind("[::1]:0").is_err() {
+            // no IPv6 on this host
+            return;
+        }
+
+        let listener = WSServerBuilder::new()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .bind("[::1]:0".parse().unwrap())
+            .build(server())
+            .unwrap();
+        let addrs = listener.local_addrs().unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addrs[0]);
+        assert!(addrs[0].is_ipv4() && addrs[1].is_ipv6());
+
+        let accepted = thread::spawn(move || {
+            (0..2)
+                .map(|_| listener.accept().unwrap().ws_stream.info().peer_addr)
+                .collect::<Vec<Option<SocketAddr>>>()
+        });
+
+        let _v6 = WSClient::connect(&format!("ws://{}/", addrs[1]), client()).unwrap();
+        let _v4 = WSClient::connect(&format!("ws://{}/", addrs[0]), client()).unwrap();
+
+        let peers = accepted.join().unwrap();
+        assert!(peers[0].unwrap().is_ipv6());
+        assert!(peers[1].unwrap().is_ipv4());
+    }
+
+    #[test]
+    fn test_socket_options() {
+        let options = SocketOptions {
+            nodelay: true,
+            ..Default::default()
+        };
+        let listener = WSServerBuilder::new()
+            .bind_all(["127.0.0.1:0".parse().unwrap()])
+            .backlog(16)
+            .socket_options(options)
+            .build(server())
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = thread::spawn(move || listener.accept().unwrap());
+
+        let client = WSClientBuilder::new(&format!("ws://{}/", addr))
+            .socket_options(options)
+            .connect(client())
+            .unwrap();
+        assert!(client.ws_stream.stream.nodelay().unwrap());
+        assert!(accepted.join().unwrap().ws_stream.stream.nodelay().unwrap());
+    }
+
+    #[test]
+    fn test_drop_frees_the_port() {
+        let listener = WSServerBuilder::new()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build(server())
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        assert!(WSServerBuilder::new().bind(addr).build(server()).is_ok());
+    }
+
+    #[test]
+    fn test_bind_errors() {
+        assert_eq!(
+            WSServerBuilder::new().build(server()).err(),
+            Some("No address to bind".to_string())
+        );
+
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+        let err = WSServerBuilder::new().bind(addr).build(server()).err();
+        assert!(err
+            .unwrap()
+            .starts_with(&format!("Failed to bind {}", addr)));
+    }
+}
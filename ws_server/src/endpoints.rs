@@ -0,0 +1,183 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::errors::HTTPStatus;
+use crate::static_files::StaticFiles;
+use ws_core::http_utils::Headers;
+
+/// Answers a plain HTTP request
+pub type HttpHandler = Arc<dyn Fn(&HttpRequest) -> HttpResponse + Send + Sync>;
+
+/// A request without `Upgrade: websocket`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Path of the request-target, normalized the same as `WSUpgrade::path`
+    pub path: String,
+    /// Decoded query parameters, in order
+    pub query: Vec<(String, String)>,
+    pub headers: Headers,
+    pub peer_addr: Option<SocketAddr>,
+}
+
+impl HttpRequest {
+    /// First value of the query parameter `name`
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Sent with `Content-Length` and `Connection: close`, the connection is closed after it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Empty response with `status`
+    pub fn new(status: u16) -> Self {
+        HttpResponse {
+            status,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    /// 200 with a plain text body
+    pub fn text(body: &str) -> Self {
+        HttpResponse::new(200)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body.as_bytes().to_vec())
+    }
+
+    /// 200 with a JSON body, `body` is sent as is
+    pub fn json(body: &str) -> Self {
+        HttpResponse::new(200)
+            .header("Content-Type", "application/json")
+            .body(body.as_bytes().to_vec())
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Status line, headers and the body unless `head_only`, as answered to `HEAD`
+    pub fn to_bytes(&self, head_only: bool) -> Vec<u8> {
+        let status = HTTPStatus::from_code(self.status);
+        let mut res = format!("HTTP/1.1 {} {}\r\n", self.status, status.reason());
+        for (name, value) in &self.headers {
+            res.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        res.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        res.push_str("Connection: close\r\n\r\n");
+
+        let mut res = res.into_bytes();
+        if !head_only {
+            res.extend_from_slice(&self.body);
+        }
+        res
+    }
+}
+
+/// Plain HTTP served on the WebSocket port, e.g. load-balancer health checks. Paths are matched
+/// exactly, without the query, then static directories by prefix. Requests no endpoint answers
+/// get a 404, unless there is a fallback.
+#[derive(Clone, Default)]
+pub struct Endpoints {
+    routes: Vec<(String, HttpHandler)>,
+    dirs: Vec<(String, StaticFiles)>,
+    fallback: Option<HttpHandler>,
+}
+
+impl Endpoints {
+    pub fn new() -> Self {
+        Endpoints::default()
+    }
+
+    /// Answers `200 ok` as long as the server accepts connections
+    pub fn health(self, path: &str) -> Self {
+        self.text(path, "ok")
+    }
+
+    /// Answers `200 ready` while `ready` returns true and `503 not ready` otherwise
+    pub fn readiness<F>(self, path: &str, ready: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.route(path, move |_| {
+            if ready() {
+                HttpResponse::text("ready")
+            } else {
+                HttpResponse::text("not ready").status(503)
+            }
+        })
+    }
+
+    /// Answers with the same plain text every time
+    pub fn text(self, path: &str, body: &str) -> Self {
+        let res = HttpResponse::text(body);
+        self.route(path, move |_| res.clone())
+    }
+
+    /// Answers requests to `path` with `handler`, whatever their method
+    pub fn route<F>(mut self, path: &str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.routes.push((path.to_string(), Arc::new(handler)));
+        self
+    }
+
+    /// Serves the files under `dir` for the paths under `prefix`, e.g. `static_dir("/", "dist")`
+    /// for a single page app. For development, see `StaticFiles`.
+    pub fn static_dir<P: Into<PathBuf>>(mut self, prefix: &str, dir: P) -> Self {
+        self.dirs.push((
+            prefix.trim_end_matches('/').to_string(),
+            StaticFiles::new(dir),
+        ));
+        self
+    }
+
+    /// Answers the requests no route matches
+    pub fn fallback<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.fallback = Some(Arc::new(handler));
+        self
+    }
+
+    pub(crate) fn resolve(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        if let Some((_, handler)) = self.routes.iter().find(|(path, _)| *path == req.path) {
+            return Some(handler(req));
+        }
+
+        for (prefix, files) in &self.dirs {
+            match req.path.strip_prefix(prefix.as_str()) {
+                Some(rel) if rel.is_empty() || rel.starts_with('/') => {
+                    return Some(files.serve(req, rel))
+                }
+                _ => (),
+            }
+        }
+
+        self.fallback.as_ref().map(|h| h(req))
+    }
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use crate::endpoints::{Endpoints, HttpRequest, HttpResponse};
+    use crate::server::WSServerListener;
+    use crate::test_common::{client, server};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use ws_client::client::WSClient;
+    use ws_core::http_utils::Headers;
+
+    fn get(addr: SocketAddr, method: &str, target: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let req = format!("{} {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", method, target);
+        stream.write_all(req.as_bytes()).unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_response_bytes() {
+        let res = HttpResponse::json("{\"ok\":true}").header("Cache-Control", "no-store");
+
+        assert_eq!(
+            String::from_utf8(res.to_bytes(false)).unwrap(),
+            "HTTP/1.1 200 OK\r\n\
+            Content-Type: application/json\r\n\
+            Cache-Control: no-store\r\n\
+            Content-Length: 11\r\n\
+            Connection: close\r\n\r\n\
+            {\"ok\":true}"
+        );
+        assert!(String::from_utf8(res.to_bytes(true))
+            .unwrap()
+            .ends_with("Content-Length: 11\r\nConnection: close\r\n\r\n"));
+        assert!(HttpResponse::new(404)
+            .to_bytes(false)
+            .starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let endpoints = Endpoints::new()
+            .health("/healthz")
+            .fallback(|req| HttpResponse::text(&format!("no {}", req.path)).status(404));
+        let req = |path: &str| HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: vec![],
+            headers: Headers::new(),
+            peer_addr: None,
+        };
+
+        assert_eq!(endpoints.resolve(&req("/healthz")).unwrap().body, b"ok");
+        let res = endpoints.resolve(&req("/other")).unwrap();
+        assert_eq!(res.status, 404);
+        assert_eq!(res.body, b"no /other");
+        assert!(Endpoints::new().resolve(&req("/healthz")).is_none());
+    }
+
+    #[test]
+    fn test_endpoints_next_to_upgrades() {
+        let ready = Arc::new(AtomicBool::new(false));
+        let is_ready = ready.clone();
+
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_endpoints(
+            Endpoints::new()
+                .health("/healthz")
+                .readiness("/ready", move || is_ready.load(Ordering::SeqCst))
+                .route("/api/echo", |req| {
+                    HttpResponse::json(&format!(
+                        "{{\"name\":\"{}\"}}",
+                        req.query_param("name").unwrap_or_default()
+                    ))
+                }),
+        );
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let first = listener.accept().unwrap().ws_stream.info().clone();
+            let second = listener.accept().unwrap().ws_stream.info().clone();
+            (first, second)
+        });
+
+        let res = get(addr, "GET", "/healthz");
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with("\r\n\r\nok"));
+
+        assert!(get(addr, "GET", "/ready").starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        ready.store(true, Ordering::SeqCst);
+        assert!(get(addr, "GET", "/ready").ends_with("\r\n\r\nready"));
+
+        let res = get(addr, "HEAD", "/api/echo?name=ws");
+        assert!(res.contains("\r\nContent-Length: 13\r\n"));
+        assert!(res.ends_with("\r\n\r\n"));
+        assert!(get(addr, "GET", "/api/echo?name=ws").ends_with("{\"name\":\"ws\"}"));
+
+        // upgrades on the same port still work
+        let _client = WSClient::connect(&format!("ws://{}/chat", addr), client()).unwrap();
+
+        // nothing answers this one
+        let res = get(addr, "GET", "/missing");
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(res.ends_with("\r\n\r\nNot Found"));
+
+        // and the listener carries on
+        let _client = WSClient::connect(&format!("ws://{}/after", addr), client()).unwrap();
+
+        let (first, second) = server.join().unwrap();
+        assert_eq!(first.path, "/chat");
+        assert_eq!(second.path, "/after");
+    }
+}
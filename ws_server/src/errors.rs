@@ -5,7 +5,73 @@ use std::fmt::Formatter;
 pub enum HTTPStatus {
     BadRequest,
     Unauthorised,
+    Forbidden,
+    NotFound,
     MethodNotAllowed,
+    UpgradeRequired,
+    TooManyRequests,
+    ServiceUnavailable,
+    /// Any other status code
+    Other(u16),
+}
+
+impl HTTPStatus {
+    /// The variant for `code`, `Other` when it has none
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            400 => HTTPStatus::BadRequest,
+            401 => HTTPStatus::Unauthorised,
+            403 => HTTPStatus::Forbidden,
+            404 => HTTPStatus::NotFound,
+            405 => HTTPStatus::MethodNotAllowed,
+            426 => HTTPStatus::UpgradeRequired,
+            429 => HTTPStatus::TooManyRequests,
+            503 => HTTPStatus::ServiceUnavailable,
+            code => HTTPStatus::Other(code),
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            HTTPStatus::BadRequest => 400,
+            HTTPStatus::Unauthorised => 401,
+            HTTPStatus::Forbidden => 403,
+            HTTPStatus::NotFound => 404,
+            HTTPStatus::MethodNotAllowed => 405,
+            HTTPStatus::UpgradeRequired => 426,
+            HTTPStatus::TooManyRequests => 429,
+            HTTPStatus::ServiceUnavailable => 503,
+            HTTPStatus::Other(code) => *code,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            HTTPStatus::BadRequest => "Bad Request",
+            HTTPStatus::Unauthorised => "Unauthorized",
+            HTTPStatus::Forbidden => "Forbidden",
+            HTTPStatus::NotFound => "Not Found",
+            HTTPStatus::MethodNotAllowed => "Method Not Allowed",
+            HTTPStatus::UpgradeRequired => "Upgrade Required",
+            HTTPStatus::TooManyRequests => "Too Many Requests",
+            HTTPStatus::ServiceUnavailable => "Service Unavailable",
+            HTTPStatus::Other(code) => match code {
+                200 => "OK",
+                204 => "No Content",
+                301 => "Moved Permanently",
+                302 => "Found",
+                304 => "Not Modified",
+                307 => "Temporary Redirect",
+                308 => "Permanent Redirect",
+                408 => "Request Timeout",
+                409 => "Conflict",
+                410 => "Gone",
+                431 => "Request Header Fields Too Large",
+                500 => "Internal Server Error",
+                _ => "Error",
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -13,6 +79,29 @@ pub struct HTTPError {
     pub code: u16,
     pub message: String,
     pub status: HTTPStatus,
+    /// Sent along with the response, e.g. `Retry-After`
+    pub headers: Vec<(String, String)>,
+}
+
+impl HTTPError {
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Full HTTP/1.1 response with the message as a plain text body, the connection is closed
+    /// after it
+    pub fn to_response(&self) -> Vec<u8> {
+        let mut res = format!("HTTP/1.1 {} {}\r\n", self.code, self.status.reason());
+        for (name, value) in &self.headers {
+            res.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        res.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+        res.push_str(&format!("Content-Length: {}\r\n", self.message.len()));
+        res.push_str("Connection: close\r\n\r\n");
+        res.push_str(&self.message);
+        res.into_bytes()
+    }
 }
 
 impl fmt::Display for HTTPError {
@@ -25,18 +114,24 @@ impl fmt::Display for HTTPError {
     }
 }
 
-pub fn get_bad_request(msg: &str) -> HTTPError {
+pub fn get_http_error(status: HTTPStatus, msg: &str) -> HTTPError {
     HTTPError {
         message: msg.to_string(),
-        code: 400,
-        status: HTTPStatus::BadRequest,
+        code: status.code(),
+        status,
+        headers: vec![],
     }
 }
 
+pub fn get_bad_request(msg: &str) -> HTTPError {
+    get_http_error(HTTPStatus::BadRequest, msg)
+}
+
 pub fn get_not_allowed(msg: &str) -> HTTPError {
-    HTTPError {
-        message: msg.to_string(),
-        code: 405,
-        status: HTTPStatus::MethodNotAllowed,
-    }
+    get_http_error(HTTPStatus::MethodNotAllowed, msg).with_header("Allow", "GET")
+}
+
+/// 426 telling the client which websocket version to retry with
+pub fn get_upgrade_required(msg: &str) -> HTTPError {
+    get_http_error(HTTPStatus::UpgradeRequired, msg).with_header("Sec-WebSocket-Version", "13")
 }
@@ -0,0 +1,63 @@
+use http::{Request, Response, StatusCode, Version};
+
+use crate::errors::{get_bad_request, HTTPError};
+use crate::server::WSUpgrade;
+use ws_core::http_utils::Headers;
+
+impl WSUpgrade {
+    /// Same as `from_parts` for a request parsed by a server built on the `http` types, the
+    /// body is ignored
+    pub fn from_request<B>(request: &Request<B>) -> Result<Self, HTTPError> {
+        if request.version() != Version::HTTP_11 {
+            return Err(get_bad_request("Upgrades need HTTP/1.1"));
+        }
+
+        let mut headers = Headers::new();
+        for (name, value) in request.headers() {
+            match value.to_str() {
+                Ok(v) => headers.append(name.as_str(), v),
+                Err(_) => return Err(get_bad_request(&format!("Invalid header value <{}>", name))),
+            }
+        }
+
+        WSUpgrade::from_parts(
+            request.method().as_str(),
+            &request.uri().to_string(),
+            headers,
+        )
+    }
+
+    /// The 101 for the server the request came from to send, fails when a header added with
+    /// `add_response_header` is not valid
+    pub fn to_http_response(&self) -> Result<Response<()>, String> {
+        let mut builder = Response::builder().status(StatusCode::SWITCHING_PROTOCOLS);
+        for (name, value) in self.response_fields() {
+            builder = builder.header(name, value);
+        }
+
+        match builder.body(()) {
+            Ok(r) => Ok(r),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+impl HTTPError {
+    /// Same as `to_response` for a server built on the `http` types, which sets the length
+    /// and connection headers itself. A 500 when the status or a header is not valid.
+    pub fn to_http_response(&self) -> Response<String> {
+        let mut builder = Response::builder().status(self.code);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+
+        builder
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(self.message.clone())
+            .unwrap_or_else(|_| {
+                let mut res = Response::new(self.message.clone());
+                *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                res
+            })
+    }
+}
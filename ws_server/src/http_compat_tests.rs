@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod tests {
+    use crate::errors::{get_upgrade_required, HTTPStatus};
+    use crate::server::{SubprotocolPolicy, WSUpgrade};
+    use http::{Request, StatusCode, Version};
+
+    fn request() -> http::request::Builder {
+        Request::builder()
+            .uri("/chat?room=42")
+            .header("Host", "127.0.0.1")
+            .header("Connection", "keep-alive, Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+    }
+
+    #[test]
+    fn test_upgrade_from_request() {
+        let req = request()
+            .header("Sec-WebSocket-Protocol", "v1.chat")
+            .body(())
+            .unwrap();
+
+        let mut ws_upgrade = WSUpgrade::from_request(&req).unwrap();
+        assert_eq!(ws_upgrade.path(), "/chat");
+        assert_eq!(ws_upgrade.query(), [("room".to_string(), "42".to_string())]);
+        ws_upgrade.select_protocol(&SubprotocolPolicy::Preference(vec!["v1.chat".to_string()]));
+        ws_upgrade.add_response_header("Set-Cookie", "seen=1");
+
+        let res = ws_upgrade.to_http_response().unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        let headers = res.headers();
+        assert_eq!(headers["upgrade"], "websocket");
+        assert_eq!(headers["connection"], "Upgrade");
+        assert_eq!(
+            headers["sec-websocket-accept"],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(headers["sec-websocket-protocol"], "v1.chat");
+        assert_eq!(headers["set-cookie"], "seen=1");
+
+        ws_upgrade.add_response_header("Bad Name", "x");
+        assert!(ws_upgrade.to_http_response().is_err());
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let old = request().version(Version::HTTP_10).body(()).unwrap();
+        let e = WSUpgrade::from_request(&old).unwrap_err();
+        assert_eq!(e.status, HTTPStatus::BadRequest);
+
+        let post = request().method("POST").body(()).unwrap();
+        let e = WSUpgrade::from_request(&post).unwrap_err();
+        assert_eq!(e.status, HTTPStatus::MethodNotAllowed);
+
+        let no_key = Request::builder()
+            .uri("/chat")
+            .header("Host", "127.0.0.1")
+            .body(())
+            .unwrap();
+        let e = WSUpgrade::from_request(&no_key).unwrap_err();
+        let res = e.to_http_response();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.body(), &e.message);
+
+        let res = get_upgrade_required("Version not supported").to_http_response();
+        assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(res.headers()["sec-websocket-version"], "13");
+    }
+}
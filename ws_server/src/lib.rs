@@ -1,3 +1,25 @@
+pub mod auth;
+mod auth_tests;
+pub mod builder;
+mod builder_tests;
+pub mod endpoints;
+mod endpoints_tests;
 pub mod errors;
+#[cfg(feature = "http")]
+pub mod http_compat;
+#[cfg(feature = "http")]
+mod http_compat_tests;
+pub mod origin;
+mod origin_tests;
+pub mod router;
+mod router_tests;
 pub mod server;
 mod server_tests;
+pub mod static_files;
+mod static_files_tests;
+#[cfg(test)]
+mod test_common;
+#[cfg(feature = "rustls")]
+pub mod tls;
+#[cfg(feature = "rustls")]
+mod tls_tests;
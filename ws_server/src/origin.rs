@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+/// Decides whether an `Origin` is allowed
+pub type OriginPredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Which web pages may open a connection, checked against the `Origin` header browsers send
+/// so a page of another site cannot connect with the user's cookies. Upgrades from an origin
+/// not allowed are rejected with 403.
+#[derive(Clone, Default)]
+pub struct OriginPolicy {
+    origins: Vec<String>,
+    allow_missing: bool,
+    predicate: Option<OriginPredicate>,
+}
+
+impl OriginPolicy {
+    /// Allows nothing until origins are added
+    pub fn new() -> Self {
+        OriginPolicy::default()
+    }
+
+    /// Allows an exact origin like `https://app.example.com`, or every subdomain with
+    /// `https://*.example.com`. Scheme and port have to match.
+    pub fn allow(mut self, origin: &str) -> Self {
+        self.origins
+            .push(origin.trim_end_matches('/').to_ascii_lowercase());
+        self
+    }
+
+    /// Allows requests without `Origin`, as sent by native clients that are not browsers
+    pub fn allow_missing(mut self, allow: bool) -> Self {
+        self.allow_missing = allow;
+        self
+    }
+
+    /// Also allows the origins `predicate` returns true for
+    pub fn allow_if<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    pub fn is_allowed(&self, origin: Option<&str>) -> bool {
+        let origin = match origin {
+            Some(o) => o.to_ascii_lowercase(),
+            None => return self.allow_missing,
+        };
+
+        self.origins.iter().any(|o| origin_matches(o, &origin))
+            || self.predicate.as_ref().is_some_and(|p| p(&origin))
+    }
+}
+
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let (p_scheme, p_host) = match pattern.split_once("://") {
+        Some(p) => p,
+        None => return false,
+    };
+    let (scheme, host) = match origin.split_once("://") {
+        Some(o) => o,
+        None => return false,
+    };
+    if p_scheme != scheme {
+        return false;
+    }
+
+    match p_host.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => p_host == host,
+    }
+}
@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use crate::origin::OriginPolicy;
+    use crate::server::WSServerListener;
+    use crate::test_common::{client, server, upgrade_request};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use ws_client::builder::WSClientBuilder;
+
+    #[test]
+    fn test_exact_and_wildcard_origins() {
+        let policy = OriginPolicy::new()
+            .allow("https://app.example.com")
+            .allow("https://*.example.org")
+            .allow("http://localhost:3000/");
+
+        assert!(policy.is_allowed(Some("https://app.example.com")));
+        assert!(policy.is_allowed(Some("HTTPS://App.Example.com")));
+        assert!(policy.is_allowed(Some("http://localhost:3000")));
+        assert!(!policy.is_allowed(Some("http://app.example.com")));
+        assert!(!policy.is_allowed(Some("https://app.example.com:8443")));
+        assert!(!policy.is_allowed(Some("https://evil-app.example.com")));
+        assert!(!policy.is_allowed(Some("http://localhost:3001")));
+
+        assert!(policy.is_allowed(Some("https://a.example.org")));
+        assert!(policy.is_allowed(Some("https://a.b.example.org")));
+        assert!(!policy.is_allowed(Some("https://example.org")));
+        assert!(!policy.is_allowed(Some("https://badexample.org")));
+        assert!(!policy.is_allowed(Some("https://a.example.org:444")));
+
+        assert!(!policy.is_allowed(Some("null")));
+        assert!(!policy.is_allowed(None));
+    }
+
+    #[test]
+    fn test_missing_origin_and_predicate() {
+        let policy = OriginPolicy::new()
+            .allow_missing(true)
+            .allow_if(|o| o.ends_with(".internal"));
+
+        assert!(policy.is_allowed(None));
+        assert!(policy.is_allowed(Some("http://dashboard.internal")));
+        assert!(!policy.is_allowed(Some("https://example.com")));
+    }
+
+    #[test]
+    fn test_disallowed_origin_rejected() {
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_origin_policy(OriginPolicy::new().allow("https://app.example.com"));
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(upgrade_request("/chat", "Origin: https://evil.example.net\r\n").as_bytes())
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+
+        assert!(res.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(res.ends_with("\r\n\r\nOrigin not allowed"));
+
+        // the listener is still there for allowed origins
+        let _client = WSClientBuilder::new(&format!("ws://{}/chat", addr))
+            .origin("https://app.example.com")
+            .connect(client())
+            .unwrap();
+        assert_eq!(server.join().unwrap().path, "/chat");
+    }
+}
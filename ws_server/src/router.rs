@@ -0,0 +1,144 @@
+use percent_encoding::percent_decode_str;
+use std::sync::Arc;
+
+use ws_core::conn_info::ConnectionInfo;
+
+/// Builds a handler for each connection, from the path parameters and everything else known
+/// about it once the upgrade request is read
+pub type HandlerFactory<H> = Arc<dyn Fn(&ConnectionInfo) -> H + Send + Sync>;
+
+enum Route<H> {
+    Handler(Arc<H>),
+    Factory(HandlerFactory<H>),
+}
+
+// by hand, as derive would want `H: Clone`
+impl<H> Clone for Route<H> {
+    fn clone(&self) -> Self {
+        match self {
+            Route::Handler(h) => Route::Handler(h.clone()),
+            Route::Factory(f) => Route::Factory(f.clone()),
+        }
+    }
+}
+
+impl<H> Route<H> {
+    fn handler(&self, info: &ConnectionInfo) -> Arc<H> {
+        match self {
+            Route::Handler(h) => h.clone(),
+            Route::Factory(f) => Arc::new(f(info)),
+        }
+    }
+}
+
+/// Maps request paths to handlers, the first matching pattern wins. A pattern is made of
+/// segments, `:name` matches any single segment and `*` as the last one matches the rest of
+/// the path, e.g. `/rooms/:id` or `/feeds/*`. The matched values end up in
+/// `ConnectionInfo::params`, the `*` one under `"*"`.
+///
+/// To serve handlers of different types use `Box<dyn WSHandler + Send + Sync>` as `H`.
+pub struct Router<H> {
+    routes: Vec<(Vec<String>, Route<H>)>,
+    fallback: Option<Route<H>>,
+}
+
+impl<H> Clone for Router<H> {
+    fn clone(&self) -> Self {
+        Router {
+            routes: self.routes.clone(),
+            fallback: self.fallback.clone(),
+        }
+    }
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Router {
+            routes: vec![],
+            fallback: None,
+        }
+    }
+}
+
+impl<H> Router<H> {
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    /// Shares `handler` between every connection to a path matching `pattern`
+    pub fn route(mut self, pattern: &str, handler: H) -> Self {
+        self.routes
+            .push((segments(pattern), Route::Handler(Arc::new(handler))));
+        self
+    }
+
+    /// Creates a handler with `factory` for every connection to a path matching `pattern`
+    pub fn route_factory<F>(mut self, pattern: &str, factory: F) -> Self
+    where
+        F: Fn(&ConnectionInfo) -> H + Send + Sync + 'static,
+    {
+        self.routes
+            .push((segments(pattern), Route::Factory(Arc::new(factory))));
+        self
+    }
+
+    /// Handler for the paths no pattern matches, they are rejected with 404 without one
+    pub fn fallback(mut self, handler: H) -> Self {
+        self.fallback = Some(Route::Handler(Arc::new(handler)));
+        self
+    }
+
+    /// Matches `path` and returns the handler for the connection, `info.params` is filled in
+    /// before a factory is called
+    pub(crate) fn resolve(&self, path: &str, info: &mut ConnectionInfo) -> Option<Arc<H>> {
+        let path = segments(path);
+
+        for (pattern, route) in &self.routes {
+            if let Some(params) = match_pattern(pattern, &path) {
+                info.params = params;
+                return Some(route.handler(info));
+            }
+        }
+
+        self.fallback.as_ref().map(|route| route.handler(info))
+    }
+}
+
+fn segments(path: &str) -> Vec<String> {
+    match path.trim_start_matches('/') {
+        "" => vec![],
+        p => p.split('/').map(|s| s.to_string()).collect(),
+    }
+}
+
+fn match_pattern(pattern: &[String], path: &[String]) -> Option<Vec<(String, String)>> {
+    let mut params = vec![];
+
+    for (i, segment) in pattern.iter().enumerate() {
+        if segment == "*" && i == pattern.len() - 1 {
+            let rest: Vec<String> = path[i.min(path.len())..]
+                .iter()
+                .map(|s| decode(s))
+                .collect();
+            params.push(("*".to_string(), rest.join("/")));
+            return Some(params);
+        }
+
+        let value = path.get(i)?;
+        match segment.strip_prefix(':') {
+            Some(name) if !value.is_empty() => params.push((name.to_string(), decode(value))),
+            Some(_) => return None,
+            None if segment == value => (),
+            None => return None,
+        }
+    }
+
+    match pattern.len() == path.len() {
+        true => Some(params),
+        false => None,
+    }
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().to_string()
+}
@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod tests {
+    use crate::router::Router;
+    use crate::server::WSServerListener;
+    use crate::test_common::{client, upgrade_request};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+    use ws_client::client::WSClient;
+    use ws_core::conn_info::ConnectionInfo;
+    use ws_core::data_frame_tx::Agent;
+    use ws_core::WSHandler;
+
+    struct Named {
+        name: String,
+    }
+
+    impl WSHandler for Named {
+        fn who(&self) -> Agent {
+            Agent::Server
+        }
+
+        fn handle_text_msg(&self, _msg: String) {}
+
+        fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+    }
+
+    fn named(name: &str) -> Named {
+        Named {
+            name: name.to_string(),
+        }
+    }
+
+    fn resolve(router: &Router<Named>, path: &str) -> Option<(String, ConnectionInfo)> {
+        let mut info = ConnectionInfo::default();
+        router
+            .resolve(path, &mut info)
+            .map(|h| (h.name.clone(), info))
+    }
+
+    #[test]
+    fn test_static_and_param_routes() {
+        let router = Router::new()
+            .route("/", named("root"))
+            .route("/rooms/lobby", named("lobby"))
+            .route("/rooms/:id", named("room"))
+            .route("/rooms/:id/users/:user", named("user"));
+
+        assert_eq!(resolve(&router, "/").unwrap().0, "root");
+        assert_eq!(resolve(&router, "/rooms/lobby").unwrap().0, "lobby");
+
+        let (name, info) = resolve(&router, "/rooms/42").unwrap();
+        assert_eq!(name, "room");
+        assert_eq!(info.param("id"), Some("42"));
+
+        let (name, info) = resolve(&router, "/rooms/a%20b/users/bob").unwrap();
+        assert_eq!(name, "user");
+        assert_eq!(info.param("id"), Some("a b"));
+        assert_eq!(info.param("user"), Some("bob"));
+
+        assert!(resolve(&router, "/rooms").is_none());
+        assert!(resolve(&router, "/rooms/").is_none());
+        assert!(resolve(&router, "/rooms/42/users").is_none());
+        assert!(resolve(&router, "/other").is_none());
+    }
+
+    #[test]
+    fn test_wildcard_and_fallback() {
+        let router = Router::new()
+            .route("/feeds/*", named("feeds"))
+            .fallback(named("fallback"));
+
+        let (name, info) = resolve(&router, "/feeds/prices/eur").unwrap();
+        assert_eq!(name, "feeds");
+        assert_eq!(info.param("*"), Some("prices/eur"));
+
+        let (name, info) = resolve(&router, "/feeds").unwrap();
+        assert_eq!(name, "feeds");
+        assert_eq!(info.param("*"), Some(""));
+
+        let (name, info) = resolve(&router, "/chat").unwrap();
+        assert_eq!(name, "fallback");
+        assert!(info.params.is_empty());
+    }
+
+    #[test]
+    fn test_factory_sees_params() {
+        let router = Router::new().route_factory("/rooms/:id", |info: &ConnectionInfo| {
+            named(&format!("room {}", info.param("id").unwrap()))
+        });
+
+        assert_eq!(resolve(&router, "/rooms/7").unwrap().0, "room 7");
+        assert_eq!(resolve(&router, "/rooms/8").unwrap().0, "room 8");
+    }
+
+    #[test]
+    fn test_listener_routes_by_path() {
+        // handlers of different types behind one router
+        let router = Router::<Box<dyn WSHandler + Send + Sync>>::new()
+            .route("/client", Box::new(client()))
+            .route_factory("/rooms/:id", |info| {
+                Box::new(named(info.param("id").unwrap()))
+            });
+        let listener = Arc::new(WSServerListener::with_router(0, router).unwrap());
+        let addr = listener.local_addr().unwrap();
+
+        let server = listener.clone();
+        let accepted = thread::spawn(move || {
+            let client = server.accept().unwrap();
+            client.ws_stream.info().clone()
+        });
+        let _client = WSClient::connect(&format!("ws://{}/rooms/42", addr), client()).unwrap();
+        let info = accepted.join().unwrap();
+        assert_eq!(info.path, "/rooms/42");
+        assert_eq!(info.param("id"), Some("42"));
+
+        let server = listener.clone();
+        let accepted = thread::spawn(move || server.accept().unwrap().ws_stream.info().clone());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(upgrade_request("/missing", "").as_bytes())
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        // an unknown path does not stop the listener
+        let _client = WSClient::connect(&format!("ws://{}/client", addr), client()).unwrap();
+        assert_eq!(accepted.join().unwrap().path, "/client");
+    }
+}
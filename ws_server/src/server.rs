@@ -1,19 +1,114 @@
-use std::collections::HashMap;
 use std::io;
-use std::io::{BufRead, Read, Write};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
-use std::sync::Arc;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use url::Url;
 
+use crate::auth::{Accept, AuthHook};
+use crate::builder::WSServerBuilder;
+use crate::endpoints::{Endpoints, HttpRequest, HttpResponse};
 use crate::errors;
-use crate::errors::{get_bad_request, HTTPError};
+use crate::errors::{get_bad_request, get_http_error, get_upgrade_required, HTTPError, HTTPStatus};
+use crate::origin::OriginPolicy;
+use crate::router::Router;
 use ws_core::base64::decode;
-use ws_core::http_utils::{parse_headers, validate_http_version};
+use ws_core::conn_info::ConnectionInfo;
+use ws_core::http_utils::{parse_request, read_head, HeadError, HeadLimits, Headers, RequestHead};
+use ws_core::keepalive::KeepAlive;
+#[cfg(feature = "rustls")]
+use ws_core::rustls::ServerConfig;
+use ws_core::send_queue::SendQueueConfig;
+use ws_core::socket::SocketOptions;
 use ws_core::{base64, sha1, ConnectionStatus, WSHandler, WSStream};
 
+/// Time a client has to complete the upgrade, TLS included, unless changed with
+/// `set_handshake_timeout`
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshakes under way at once unless changed with `set_max_handshakes`
+pub const DEFAULT_MAX_HANDSHAKES: usize = 256;
+
+/// Pause of an accept thread after its socket failed, so a lasting error like running out of
+/// file descriptors does not spin
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Picks one of the offered subprotocols or none
+pub type SelectProtocol = Arc<dyn Fn(&[String]) -> Option<String> + Send + Sync>;
+
+/// Picks the extensions to accept out of the offered ones, each with the parameters to answer
+/// with, e.g. `["x-trace; id=1"]`
+pub type SelectExtensions = Arc<dyn Fn(&[String]) -> Vec<String> + Send + Sync>;
+
+/// How the server picks one of the subprotocols a client offers
+#[derive(Clone)]
+pub enum SubprotocolPolicy {
+    /// The first of these the client offered, in the server's order of preference
+    Preference(Vec<String>),
+    /// Called with the offered protocols, returns one of them or `None`
+    Select(SelectProtocol),
+}
+
+/// What the listener decides on during an upgrade, plain or TLS
+#[derive(Clone, Default)]
+pub(crate) struct UpgradePolicy {
+    pub(crate) subprotocols: Option<SubprotocolPolicy>,
+    pub(crate) extensions: Option<SelectExtensions>,
+    /// The only extensions `extensions` may accept
+    pub(crate) allowed_extensions: Vec<String>,
+    pub(crate) auth: Option<AuthHook>,
+    pub(crate) origin: Option<OriginPolicy>,
+    pub(crate) http: Option<Endpoints>,
+    pub(crate) limits: HeadLimits,
+    pub(crate) timeout: Option<Duration>,
+}
+
+/// What the accept and handshake threads hand over to `accept`
+enum Incoming<H> {
+    Connection(TcpStream),
+    Upgraded(WSStream<H>),
+    Failed(io::Error),
+}
+
+/// Everything a connection is upgraded with. Each handshake thread holds on to the one it
+/// started with, the setters change a copy.
+struct Upgrader<H> {
+    socket: SocketOptions,
+    router: Router<H>,
+    policy: UpgradePolicy,
+    keepalive: Option<KeepAlive>,
+    send_queue: Option<SendQueueConfig>,
+    max_frame_size: usize,
+    max_message_size: usize,
+    #[cfg(feature = "rustls")]
+    tls: Option<Arc<ServerConfig>>,
+}
+
 pub struct WSServerListener<H> {
-    listener: TcpListener,
-    handler: Arc<H>,
+    local_addrs: Vec<SocketAddr>,
+    /// Fed by one accept thread per listening socket and by the handshake threads
+    incoming: Mutex<Receiver<Incoming<H>>>,
+    upgraded: Sender<Incoming<H>>,
+    /// Tells the accept threads to stop once the listener is dropped
+    closed: Arc<AtomicBool>,
+    acceptors: Vec<JoinHandle<()>>,
+    upgrader: Arc<Upgrader<H>>,
+    /// Handshake threads running, at most `max_handshakes`
+    handshakes: Arc<AtomicUsize>,
+    max_handshakes: usize,
+}
+
+/// Counts a handshake thread for as long as it runs
+struct HandshakeSlot(Arc<AtomicUsize>);
+
+impl Drop for HandshakeSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 pub struct IncomingClient<'a, H> {
@@ -27,113 +122,551 @@ pub struct ConnectedClient<H> {
 
 impl<H> WSServerListener<H>
 where
-    H: WSHandler,
+    H: WSHandler + Send + Sync + 'static,
 {
+    /// Serves `handler` on every path on `127.0.0.1`, see `WSServerBuilder` for other
+    /// addresses
     pub fn init(port: u16, handler: H) -> Result<WSServerListener<H>, String>
     where
         H: WSHandler,
     {
-        let conn: TcpListener =
-            match TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)) {
-                Ok(s) => s,
-                Err(_) => return Err("Failed bind tcp connection".to_string()),
-            };
+        WSServerListener::with_router(port, Router::new().fallback(handler))
+    }
+
+    /// Picks the handler of each connection by its path, see `Router`
+    pub fn with_router(port: u16, router: Router<H>) -> Result<WSServerListener<H>, String> {
+        WSServerBuilder::new()
+            .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+            .build_with_router(router)
+    }
+
+    pub(crate) fn from_listeners(
+        listeners: Vec<TcpListener>,
+        socket: SocketOptions,
+        router: Router<H>,
+    ) -> Result<WSServerListener<H>, String> {
+        let local_addrs = match listeners.iter().map(|l| l.local_addr()).collect() {
+            Ok(a) => a,
+            Err(e) => return Err(e.to_string()),
+        };
+        let (tx, rx) = channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let acceptors = listeners
+            .into_iter()
+            .map(|l| spawn_acceptor(l, tx.clone(), closed.clone()))
+            .collect();
 
         Ok(WSServerListener {
-            listener: conn,
-            handler: Arc::new(handler),
+            local_addrs,
+            incoming: Mutex::new(rx),
+            upgraded: tx,
+            closed,
+            acceptors,
+            upgrader: Arc::new(Upgrader {
+                socket,
+                router,
+                policy: UpgradePolicy {
+                    timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+                    ..Default::default()
+                },
+                keepalive: None,
+                send_queue: None,
+                max_frame_size: 0,
+                max_message_size: 0,
+                #[cfg(feature = "rustls")]
+                tls: None,
+            }),
+            handshakes: Arc::new(AtomicUsize::new(0)),
+            max_handshakes: DEFAULT_MAX_HANDSHAKES,
         })
     }
 
-    pub fn listen(&self) -> IncomingClient<'_, H> {
-        IncomingClient { ws_listener: self }
+    /// The settings of connections accepted from now on, handshakes under way keep theirs
+    fn upgrader(&mut self) -> &mut Upgrader<H> {
+        Arc::make_mut(&mut self.upgrader)
     }
 
-    pub fn accept(&self) -> Result<ConnectedClient<H>, String>
+    /// Keepalive applied to every connection accepted from now on
+    pub fn set_keepalive(&mut self, keepalive: KeepAlive) {
+        self.upgrader().keepalive = Some(keepalive);
+    }
+
+    /// Gives every connection accepted from now on a bounded send queue
+    pub fn set_send_queue(&mut self, config: SendQueueConfig) {
+        self.upgrader().send_queue = Some(config);
+    }
+
+    /// See `WSStream::set_max_frame_size`, applied to every connection accepted from now on
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        self.upgrader().max_frame_size = size;
+    }
+
+    /// See `WSStream::set_max_message_size`, applied to every connection accepted from now on
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.upgrader().max_message_size = size;
+    }
+
+    /// Negotiates a subprotocol with every client offering some, the chosen one is sent back in
+    /// `Sec-WebSocket-Protocol` and set as `ConnectionInfo::protocol`. When none fits the
+    /// upgrade goes on without a subprotocol.
+    pub fn set_subprotocols(&mut self, policy: SubprotocolPolicy) {
+        self.upgrader().policy.subprotocols = Some(policy);
+    }
+
+    /// Accepts the extensions `select` picks out of the ones each client offers, they are sent
+    /// back in `Sec-WebSocket-Extensions` and set as `ConnectionInfo::extensions`. Frames are not
+    /// transformed and frames with RSV bits are refused, so only the `allowed` extensions are
+    /// ever offered to `select` or accepted, which must be ones setting no RSV bits, unlike
+    /// `permessage-deflate`. What they mean is up to the handler. None are accepted by default.
+    pub fn set_extensions<F>(&mut self, allowed: &[&str], select: F)
     where
-        H: WSHandler,
+        F: Fn(&[String]) -> Vec<String> + Send + Sync + 'static,
     {
-        let req = self.listener.accept().map(|c| c.0);
-
-        match req {
-            Ok(stream) => {
-                let mut str_cpy = stream.try_clone().unwrap();
-                let _ = handshake(&mut str_cpy)?;
-                Ok(ConnectedClient {
-                    ws_state: ConnectionStatus::Open,
-                    ws_stream: WSStream {
-                        stream: str_cpy,
-                        handler: self.handler.clone(),
-                    },
-                })
+        let policy = &mut self.upgrader().policy;
+        policy.allowed_extensions = allowed.iter().map(|a| a.to_string()).collect();
+        policy.extensions = Some(Arc::new(select));
+    }
+
+    /// Rejects upgrades from origins `policy` does not allow, any origin is accepted without one
+    pub fn set_origin_policy(&mut self, policy: OriginPolicy) {
+        self.upgrader().policy.origin = Some(policy);
+    }
+
+    /// Authorizes every upgrade with `hook` before it completes, see `AuthHook`
+    pub fn set_auth<F>(&mut self, hook: F)
+    where
+        F: Fn(&WSUpgrade, &ConnectionInfo) -> Result<Accept, HTTPError> + Send + Sync + 'static,
+    {
+        self.upgrader().policy.auth = Some(Arc::new(hook));
+    }
+
+    /// Answers plain HTTP requests, the ones without `Upgrade: websocket`, with `endpoints` on
+    /// the same port, TLS included. Those no endpoint answers get a 404. `accept` goes on with
+    /// the next connection after each.
+    pub fn set_endpoints(&mut self, endpoints: Endpoints) {
+        self.upgrader().policy.http = Some(endpoints);
+    }
+
+    /// Time a client has from being accepted to sending the whole upgrade request, TLS
+    /// handshake included. Clients too slow get a 408 and are dropped. Each handshake runs on
+    /// its own thread, so a slow client only holds up itself.
+    pub fn set_handshake_timeout(&mut self, timeout: Duration) {
+        self.upgrader().policy.timeout = Some(timeout);
+    }
+
+    /// Handshakes run on threads of their own, at most `max` at once. Clients accepted while
+    /// that many are under way get a 503 and are dropped.
+    pub fn set_max_handshakes(&mut self, max: usize) {
+        self.max_handshakes = max;
+    }
+
+    /// Largest upgrade request head accepted, bigger ones get a 431
+    pub fn set_max_header_size(&mut self, size: usize) {
+        self.upgrader().policy.limits.max_size = size;
+    }
+
+    /// Serves wss://, every connection accepted from now on goes through a TLS handshake first.
+    /// See `tls::server_config` for building the config from a certificate chain and key.
+    #[cfg(feature = "rustls")]
+    pub fn set_tls(&mut self, config: Arc<ServerConfig>) {
+        self.upgrader().tls = Some(config);
+    }
+
+    /// Address of the first listening socket
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addrs[0])
+    }
+
+    /// Addresses of every listening socket, in the order they were bound
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        Ok(self.local_addrs.clone())
+    }
+
+    pub fn listen(&self) -> IncomingClient<'_, H> {
+        IncomingClient { ws_listener: self }
+    }
+
+    /// Waits for the next client to complete its upgrade, in the order they complete. Rejected
+    /// upgrades, answered plain HTTP requests and clients going away mid-handshake are logged
+    /// and skipped, only a failure of the listening socket itself is returned.
+    pub fn accept(&self) -> Result<ConnectedClient<H>, String> {
+        loop {
+            // never disconnected, `upgraded` is a sender of its own
+            let incoming = self.incoming.lock().unwrap().recv().unwrap();
+            let stream = match incoming {
+                Incoming::Connection(s) => s,
+                Incoming::Upgraded(ws_stream) => {
+                    ws_stream.handler.on_open(ws_stream.info());
+                    return Ok(ConnectedClient {
+                        ws_state: ConnectionStatus::Open,
+                        ws_stream,
+                    });
+                }
+                Incoming::Failed(e) => return Err(format!("Failed to accept a connection: {}", e)),
+            };
+
+            if self.handshakes.fetch_add(1, Ordering::SeqCst) >= self.max_handshakes {
+                self.handshakes.fetch_sub(1, Ordering::SeqCst);
+                let e = get_http_error(HTTPStatus::ServiceUnavailable, "Too many handshakes")
+                    .with_header("Retry-After", "1");
+                println!("HttpError {:?}", e);
+                reject(&mut &stream, &e);
+                continue;
             }
-            _ => Err("".to_string()),
+            let slot = HandshakeSlot(self.handshakes.clone());
+
+            let upgrader = self.upgrader.clone();
+            let upgraded = self.upgraded.clone();
+            thread::spawn(move || {
+                let _slot = slot;
+                let peer_addr = stream.peer_addr().ok();
+
+                // a plain HTTP request that was answered, or an upgrade that was rejected or
+                // failed, none of which concerns the other clients
+                match upgrader.upgrade(stream) {
+                    Ok(Some(ws_stream)) => {
+                        let _ = upgraded.send(Incoming::Upgraded(ws_stream));
+                    }
+                    Ok(None) => {}
+                    Err(e) => println!("Dropped connection from {:?}: {}", peer_addr, e),
+                }
+            });
         }
     }
 }
 
-impl<H> Iterator for IncomingClient<'_, H>
+impl<H> Clone for Upgrader<H> {
+    fn clone(&self) -> Self {
+        Upgrader {
+            socket: self.socket,
+            router: self.router.clone(),
+            policy: self.policy.clone(),
+            keepalive: self.keepalive,
+            send_queue: self.send_queue,
+            max_frame_size: self.max_frame_size,
+            max_message_size: self.max_message_size,
+            #[cfg(feature = "rustls")]
+            tls: self.tls.clone(),
+        }
+    }
+}
+
+impl<H> Upgrader<H>
 where
     H: WSHandler,
+{
+    fn upgrade(&self, stream: TcpStream) -> Result<Option<WSStream<H>>, String> {
+        if let Err(e) = self.socket.apply(&stream) {
+            return Err(e.to_string());
+        }
+        let info = ConnectionInfo {
+            peer_addr: stream.peer_addr().ok(),
+            ..Default::default()
+        };
+        let deadline = self.policy.timeout.map(|t| Instant::now() + t);
+        set_timeouts(&stream, self.policy.timeout)?;
+
+        #[cfg(feature = "rustls")]
+        if let Some(config) = &self.tls {
+            let ws_stream = crate::tls::accept(
+                stream,
+                config.clone(),
+                &self.policy,
+                deadline,
+                info,
+                |u, info| self.route(u, info),
+            )?;
+            return match ws_stream {
+                Some(mut s) => {
+                    set_timeouts(&s.stream, None)?;
+                    self.configure(&mut s)?;
+                    Ok(Some(s))
+                }
+                None => Ok(None),
+            };
+        }
+
+        let mut str_cpy = stream.try_clone().unwrap();
+        let upgraded = handshake(&mut str_cpy, &stream, &self.policy, deadline, |u| {
+            self.route(u, info)
+        })?;
+        let (_, (handler, info), rest) = match upgraded {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        set_timeouts(&stream, None)?;
+        let mut ws_stream = WSStream::new(str_cpy, handler)
+            .with_info(info)
+            .with_buffered(rest);
+        self.configure(&mut ws_stream)?;
+        Ok(Some(ws_stream))
+    }
+
+    fn configure(&self, ws_stream: &mut WSStream<H>) -> Result<(), String> {
+        ws_stream.set_max_frame_size(self.max_frame_size);
+        ws_stream.set_max_message_size(self.max_message_size);
+        if self.keepalive.is_some() {
+            ws_stream.set_keepalive(self.keepalive)?;
+        }
+        if let Some(config) = self.send_queue {
+            ws_stream.set_send_queue(config)?;
+        }
+        Ok(())
+    }
+
+    /// Completes `info` with what the upgrade request says, authorizes it and finds the
+    /// handler for its path
+    fn route(
+        &self,
+        ws_upgrade: &mut WSUpgrade,
+        mut info: ConnectionInfo,
+    ) -> Result<(Arc<H>, ConnectionInfo), HTTPError> {
+        info = ws_upgrade.connection_info(info);
+
+        if let Some(auth) = &self.policy.auth {
+            let accepted = auth(ws_upgrade, &info)?;
+            info.auth = accepted.auth;
+            ws_upgrade.response_headers.extend(accepted.headers);
+        }
+
+        match self.router.resolve(ws_upgrade.path(), &mut info) {
+            Some(handler) => Ok((handler, info)),
+            None => Err(get_http_error(HTTPStatus::NotFound, "Not Found")),
+        }
+    }
+}
+
+impl<H> Drop for WSServerListener<H> {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        // each accept thread is blocked in accept, a connection wakes it up to see it is closed.
+        // With SO_REUSEPORT another socket may get that connection, so it is not waited for.
+        for (addr, acceptor) in self.local_addrs.iter().zip(self.acceptors.drain(..)) {
+            let woken = TcpStream::connect_timeout(&wake_addr(*addr), Duration::from_secs(1));
+            if woken.is_ok() && !self.upgrader.socket.reuse_port {
+                let _ = acceptor.join();
+            }
+        }
+    }
+}
+
+/// Accepts connections on `listener` until the `WSServerListener` is dropped
+fn spawn_acceptor<H>(
+    listener: TcpListener,
+    tx: Sender<Incoming<H>>,
+    closed: Arc<AtomicBool>,
+) -> JoinHandle<()>
+where
+    H: Send + Sync + 'static,
+{
+    thread::spawn(move || loop {
+        let accepted = listener.accept();
+        if closed.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let failed = accepted.is_err();
+        let incoming = match accepted {
+            Ok((s, _)) => Incoming::Connection(s),
+            Err(e) => Incoming::Failed(e),
+        };
+        if tx.send(incoming).is_err() {
+            break;
+        }
+        if failed {
+            thread::sleep(ACCEPT_ERROR_BACKOFF);
+        }
+    })
+}
+
+/// Where to connect to reach a listener bound on `addr`
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, addr.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, addr.port()).into(),
+        _ => addr,
+    }
+}
+
+impl<H> Iterator for IncomingClient<'_, H>
+where
+    H: WSHandler + Send + Sync + 'static,
 {
     type Item = io::Result<ConnectedClient<H>>;
 
+    /// Never ends, a failure to accept a connection is yielded and the next call waits for
+    /// another client
     fn next(&mut self) -> Option<io::Result<ConnectedClient<H>>> {
         match self.ws_listener.accept() {
             Ok(l) => Some(Ok(l)),
-            _ => None,
+            Err(e) => Some(Err(io::Error::other(e))),
         }
     }
 }
 
-fn handshake(stream: &mut TcpStream) -> Result<(), String> {
+/// Reads the upgrade request and answers it, `accept` gets the last say before the 101 and
+/// its error is sent back instead. Also returns the bytes the client sent after the request,
+/// or `None` when it was a plain HTTP request answered by `policy.http`.
+pub(crate) fn handshake<S, T, F>(
+    stream: &mut S,
+    socket: &TcpStream,
+    policy: &UpgradePolicy,
+    deadline: Option<Instant>,
+    accept: F,
+) -> Result<Option<(WSUpgrade, T, Vec<u8>)>, String>
+where
+    S: Read + Write,
+    F: FnOnce(&mut WSUpgrade) -> Result<T, HTTPError>,
+{
     let mut ws_upgrade = WSUpgrade::new();
 
     println!("Initiated handshake with client");
 
-    let mut buf = [0u8; 512];
-    let r_size = match stream.read(&mut buf) {
-        Ok(n) => n,
+    let (head, rest) = match read_head(stream, socket, &policy.limits, deadline, parse_request) {
+        Ok(h) => h,
+        Err(e @ (HeadError::Closed | HeadError::Io(_))) => {
+            println!("Failed to read handshake: {}", e);
+            return Err(e.to_string());
+        }
         Err(e) => {
-            println!("Failed to read handshake: {:?}", e);
-            return Err("Handshake failed".to_string());
+            let e = head_error(e);
+            println!("HttpError {:?}", e);
+            reject(stream, &e);
+            return Err(e.message);
         }
     };
 
-    if let Err(e) = ws_upgrade.parse_handshake(buf[..r_size].to_vec()) {
+    if let Some(endpoints) = &policy.http {
+        if !head.headers.has_token("upgrade", "websocket") {
+            let (path, query) = match parse_request_target(&head.target) {
+                Ok(t) => t,
+                Err(e) => {
+                    reject(stream, &e);
+                    return Err(e.message);
+                }
+            };
+            let req = HttpRequest {
+                method: head.method.clone(),
+                path,
+                query,
+                headers: head.headers.clone(),
+                peer_addr: socket.peer_addr().ok(),
+            };
+
+            // not an upgrade either, e.g. a load balancer probing `/`
+            let res = endpoints
+                .resolve(&req)
+                .unwrap_or_else(|| HttpResponse::text("Not Found").status(404));
+            println!("Answered {} {} with {}", req.method, req.path, res.status);
+            if let Err(e) = stream
+                .write_all(&res.to_bytes(req.method == "HEAD"))
+                .and_then(|_| stream.flush())
+            {
+                println!("Failed to write response: {:?}", e);
+            }
+            return Ok(None);
+        }
+    }
+
+    if let Err(e) = ws_upgrade.parse_head(head) {
         println!("HttpError {:?}", e);
+        reject(stream, &e);
         return Err(e.message);
     };
 
+    if let Some(origin) = &policy.origin {
+        if !origin.is_allowed(ws_upgrade.origin()) {
+            let e = get_http_error(HTTPStatus::Forbidden, "Origin not allowed");
+            println!("HttpError {:?}", e);
+            reject(stream, &e);
+            return Err(e.message);
+        }
+    }
+
+    if let Some(subprotocols) = &policy.subprotocols {
+        ws_upgrade.select_protocol(subprotocols);
+    }
+    if let Some(select) = &policy.extensions {
+        ws_upgrade.select_extensions(&policy.allowed_extensions, select);
+    }
+
+    let accepted = match accept(&mut ws_upgrade) {
+        Ok(a) => a,
+        Err(e) => {
+            println!("HttpError {:?}", e);
+            reject(stream, &e);
+            return Err(e.message);
+        }
+    };
+
     let server_handshake = ws_upgrade.create_handshake();
     if let Err(e) = stream.write_all(&server_handshake) {
         println!("Failed to write handshake: {:?}", e);
         return Err("Handshake failed".to_string());
     };
 
+    Ok(Some((ws_upgrade, accepted, rest)))
+}
+
+fn set_timeouts(stream: &TcpStream, timeout: Option<Duration>) -> Result<(), String> {
+    if let Err(e) = stream.set_read_timeout(timeout) {
+        return Err(e.to_string());
+    }
+    if let Err(e) = stream.set_write_timeout(timeout) {
+        return Err(e.to_string());
+    }
     Ok(())
 }
 
+/// Answers a rejected upgrade, the connection is dropped right after so a failed write only
+/// gets logged
+fn reject<S: Write>(stream: &mut S, e: &HTTPError) {
+    if let Err(e) = stream
+        .write_all(&e.to_response())
+        .and_then(|_| stream.flush())
+    {
+        println!("Failed to write error response: {:?}", e);
+    }
+}
+
+#[derive(Debug)]
 pub struct WSUpgrade {
     key: String,
+    method: String,
     resource: String,
+    path: String,
+    query: Vec<(String, String)>,
     host: String,
     origin: String,
-    sub_protocol: String,
+    sub_protocols: Vec<String>,
+    protocol: Option<String>,
+    offered_extensions: Vec<String>,
+    /// Sent back in `Sec-WebSocket-Extensions`
     extensions: Vec<String>,
     version: u8,
+    headers: Headers,
+    /// Added to the 101 response
+    response_headers: Vec<(String, String)>,
 }
 
 impl Default for WSUpgrade {
     fn default() -> Self {
         WSUpgrade {
             resource: String::from(""),
+            path: String::from("/"),
+            query: vec![],
             host: String::from(""),
             origin: String::from(""),
             key: String::from(""),
-            sub_protocol: String::from(""),
+            sub_protocols: vec![],
+            protocol: None,
+            offered_extensions: vec![],
             extensions: vec![],
             version: 13,
+            method: String::from("GET"),
+            headers: Headers::new(),
+            response_headers: vec![],
         }
     }
 }
@@ -146,68 +679,243 @@ impl WSUpgrade {
     }
 
     pub fn parse_handshake(&mut self, c_handshake: Vec<u8>) -> Result<(), HTTPError> {
-        let h_lines: Vec<String> = c_handshake
-            .lines()
-            .map(|res| res.unwrap())
-            .take_while(|l| !l.is_empty())
-            .collect();
+        match parse_request(&c_handshake, &HeadLimits::default()) {
+            Ok((head, _)) => self.parse_head(head),
+            Err(e) => Err(head_error(e)),
+        }
+    }
+
+    /// Same as `parse_handshake` for a request head already parsed
+    pub(crate) fn parse_head(&mut self, head: RequestHead) -> Result<(), HTTPError> {
+        verify_http_method(&head.method)?;
+        let (path, query) = parse_request_target(&head.target)?;
+
+        self.method = head.method;
+        self.resource = head.target;
+        self.path = path;
+        self.query = query;
+
+        validate_headers(&head.headers)?;
 
-        let headers: HashMap<String, String> = parse_headers(&h_lines);
+        self.extract_headers_info(&head.headers);
+        self.headers = head.headers;
+
+        Ok(())
+    }
 
-        if h_lines.is_empty() {
-            return Err(get_bad_request("Invalid Request"));
+    /// Validates an upgrade request another HTTP server already parsed, `target` being the
+    /// request-target as sent, e.g. `/chat?room=1`. Answer it with `create_handshake` and hand
+    /// the connection over with `into_stream`.
+    pub fn from_parts(method: &str, target: &str, headers: Headers) -> Result<Self, HTTPError> {
+        let mut ws_upgrade = WSUpgrade::new();
+        ws_upgrade.parse_head(RequestHead {
+            method: method.to_string(),
+            target: target.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+        })?;
+        Ok(ws_upgrade)
+    }
+
+    pub fn create_handshake(&self) -> Vec<u8> {
+        let mut res = String::from("HTTP/1.1 101 Switching Protocols\r\n");
+        for (name, value) in self.response_fields() {
+            res.push_str(&format!("{}: {}\r\n", name, value));
         }
+        res.push_str("\r\n");
+        res.into_bytes()
+    }
 
-        let status: Vec<&str> = h_lines.first().unwrap().splitn(3, " ").collect();
-        if status.len() != 3 {
-            return Err(get_bad_request("Invalid HTTP Request"));
+    /// Header fields of the 101, in order
+    pub(crate) fn response_fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("Upgrade".to_string(), "websocket".to_string()),
+            ("Connection".to_string(), "Upgrade".to_string()),
+            ("Sec-WebSocket-Accept".to_string(), self.create_accept_key()),
+        ];
+        if let Some(protocol) = &self.protocol {
+            fields.push(("Sec-WebSocket-Protocol".to_string(), protocol.clone()));
         }
+        if !self.extensions.is_empty() {
+            fields.push((
+                "Sec-WebSocket-Extensions".to_string(),
+                self.extensions.join(", "),
+            ));
+        }
+        fields.extend(self.response_headers.iter().cloned());
+        fields
+    }
 
-        verify_http_method(status[0])?;
-        verify_resource_uri(status[1])?;
-        let _ = match validate_http_version(status[2]) {
-            Ok(()) => Ok(()),
-            Err(e) => Err(get_bad_request(e)),
-        };
+    /// Wraps a connection the 101 was written to, `buffered` being whatever was read from it
+    /// past the end of the request
+    pub fn into_stream<H: WSHandler>(
+        &self,
+        stream: TcpStream,
+        buffered: Vec<u8>,
+        handler: Arc<H>,
+    ) -> WSStream<H> {
+        let ws_stream = WSStream::new(stream, handler).with_buffered(buffered);
+        let info = self.connection_info(ws_stream.info().clone());
+        ws_stream.with_info(info)
+    }
 
-        self.resource = status[1].to_string();
+    /// Same as `into_stream` for a connection inside a TLS session, fails when the client
+    /// certificate cannot be read
+    #[cfg(feature = "rustls")]
+    pub fn into_tls_stream<H: WSHandler>(
+        &self,
+        stream: TcpStream,
+        conn: ws_core::rustls::Connection,
+        buffered: Vec<u8>,
+        handler: Arc<H>,
+    ) -> Result<WSStream<H>, String> {
+        let ws_stream = WSStream::new_tls(stream, conn, handler)?.with_buffered(buffered);
+        let info = self.connection_info(ws_stream.info().clone());
+        Ok(ws_stream.with_info(info))
+    }
 
-        validate_headers(&headers)?;
+    /// `info` completed with what the request and the negotiation say
+    pub(crate) fn connection_info(&self, mut info: ConnectionInfo) -> ConnectionInfo {
+        info.protocol = self.protocol.clone();
+        info.extensions = self.extensions.clone();
+        info.path = self.path.clone();
+        info.query = self.query.clone();
+        info
+    }
 
-        self.extract_headers_info(&headers);
+    /// `Origin` of the request, browsers always send one
+    pub fn origin(&self) -> Option<&str> {
+        match self.origin.as_str() {
+            "" => None,
+            o => Some(o),
+        }
+    }
 
-        Ok(())
+    pub fn method(&self) -> &str {
+        &self.method
     }
 
-    pub fn create_handshake(&self) -> Vec<u8> {
-        let mut res = vec![];
-        res.extend_from_slice("HTTP/1.1 101 Switching Protocols\n".as_bytes());
-        res.extend_from_slice("Upgrade: websocket\nConnection: Upgrade\n".as_bytes());
-        res.extend_from_slice("Sec-WebSocket-Accept: ".as_bytes());
-        res.extend_from_slice(self.create_accept_key().as_bytes());
-        res
-    }
-
-    fn extract_headers_info(&mut self, headers: &HashMap<String, String>) {
-        self.host = headers.get("host").unwrap().to_string();
-        self.version = headers
-            .get("sec-websocket-version")
-            .unwrap()
-            .parse::<u8>()
-            .unwrap();
-        self.sub_protocol = match headers.get("sec-websocket-protocol") {
-            Some(v) => v.to_string(),
-            None => String::from(""),
+    /// First value of the request header `name`, case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Cookies sent in the `Cookie` headers, in order
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        self.headers
+            .get_all("cookie")
+            .flat_map(|c| c.split(';'))
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(n, v)| (n.trim().to_string(), v.trim().trim_matches('"').to_string()))
+            .collect()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies()
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// Path of the request-target normalized like a URL's, dot segments are resolved, so
+    /// `/a/../b` is `/b`, and characters a path cannot hold are percent-encoded. Escapes already
+    /// there, `%2F` included, are kept as they are.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Decoded query parameters of the request-target, in order
+    pub fn query(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    /// Subprotocols the client offered, in its order of preference
+    pub fn offered_protocols(&self) -> &[String] {
+        &self.sub_protocols
+    }
+
+    /// Subprotocol chosen by `select_protocol`
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Picks one of the offered subprotocols, anything the client did not offer is ignored
+    pub fn select_protocol(&mut self, policy: &SubprotocolPolicy) {
+        let chosen = match policy {
+            SubprotocolPolicy::Preference(supported) => supported
+                .iter()
+                .find(|p| self.sub_protocols.contains(p))
+                .cloned(),
+            SubprotocolPolicy::Select(select) => select(&self.sub_protocols),
         };
-        self.origin = match headers.get("origin") {
-            Some(v) => v.to_string(),
-            None => String::from(""),
+
+        self.protocol = chosen.filter(|p| self.sub_protocols.contains(p));
+    }
+
+    /// Adds a header to the 101 response, after the negotiated ones
+    pub fn add_response_header(&mut self, name: &str, value: &str) {
+        self.response_headers
+            .push((name.to_string(), value.to_string()));
+    }
+
+    /// Extensions the client offered with their parameters, in its order of preference
+    pub fn offered_extensions(&self) -> &[String] {
+        &self.offered_extensions
+    }
+
+    /// Extensions chosen by `select_extensions`
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Accepts the extensions `select` returns out of the offered ones named in `allowed`, any
+    /// other or one that appears twice is dropped. Frames are read as they are, so `allowed`
+    /// must only name extensions that set no RSV bits.
+    pub fn select_extensions(&mut self, allowed: &[String], select: &SelectExtensions) {
+        let is_allowed = |ext: &str| {
+            allowed
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(extension_name(ext)))
         };
-        self.key = headers.get("sec-websocket-key").unwrap().to_string();
-        self.extensions = match headers.get("sec-websocket-extensions") {
-            Some(v) => v.split(",").map(|s| s.trim().to_string()).collect(),
-            None => vec![],
+        let offered: Vec<String> = self
+            .offered_extensions
+            .iter()
+            .filter(|e| is_allowed(e))
+            .cloned()
+            .collect();
+
+        let mut accepted: Vec<String> = vec![];
+        for ext in select(&offered) {
+            let name = extension_name(&ext);
+            let offered = offered
+                .iter()
+                .any(|o| extension_name(o).eq_ignore_ascii_case(name));
+            if offered
+                && !accepted
+                    .iter()
+                    .any(|a| extension_name(a).eq_ignore_ascii_case(name))
+            {
+                accepted.push(ext.trim().to_string());
+            }
         }
+        self.extensions = accepted;
+    }
+
+    /// Only called once `validate_headers` passed
+    fn extract_headers_info(&mut self, headers: &Headers) {
+        self.host = headers.get("host").unwrap_or_default().to_string();
+        self.version = 13;
+        self.sub_protocols = headers.tokens("sec-websocket-protocol");
+        self.origin = headers.get("origin").unwrap_or_default().to_string();
+        self.key = headers
+            .get("sec-websocket-key")
+            .unwrap_or_default()
+            .to_string();
+        self.offered_extensions = headers.tokens("sec-websocket-extensions");
     }
 
     fn create_accept_key(&self) -> String {
@@ -216,36 +924,33 @@ impl WSUpgrade {
     }
 }
 
-fn validate_headers(headers: &HashMap<String, String>) -> Result<(), HTTPError> {
-    match headers.get("host") {
-        Some(_) => (),
-        None => return Err(get_bad_request("Invalid header <Host>")),
+fn validate_headers(headers: &Headers) -> Result<(), HTTPError> {
+    match headers.get_all("host").count() {
+        1 => (),
+        _ => return Err(get_bad_request("Invalid header <Host>")),
     };
 
-    match headers.get("upgrade") {
-        Some(upgrade) => {
-            if !upgrade.eq_ignore_ascii_case("websocket") {
-                return Err(get_bad_request(
-                    "Invalid header value upgrade <upgrade: websocket>",
-                ));
-            }
-        }
-        None => return Err(get_bad_request("Invalid header <Upgrade>")),
-    };
+    if !headers.contains("upgrade") {
+        return Err(get_bad_request("Invalid header <Upgrade>"));
+    }
+    if !headers.has_token("upgrade", "websocket") {
+        return Err(get_bad_request(
+            "Invalid header value upgrade <upgrade: websocket>",
+        ));
+    }
 
-    match headers.get("connection") {
-        Some(connection) => {
-            if !connection.eq_ignore_ascii_case("upgrade") {
-                return Err(get_bad_request(
-                    "Invalid header value connection <connection: upgrade>",
-                ));
-            }
-        }
-        None => return Err(get_bad_request("Invalid header <Connection>")),
+    if !headers.contains("connection") {
+        return Err(get_bad_request("Invalid header <Connection>"));
+    }
+    if !headers.has_token("connection", "upgrade") {
+        return Err(get_bad_request(
+            "Invalid header value connection <connection: upgrade>",
+        ));
     }
 
-    match headers.get("sec-websocket-key") {
-        Some(key) => {
+    let keys: Vec<&str> = headers.get_all("sec-websocket-key").collect();
+    match keys[..] {
+        [key] => {
             if decode(key).len() != 16 {
                 return Err(get_bad_request(
                     "Invalid header value sec-websocket-key <sec-websocket-key: 16 random \
@@ -253,27 +958,57 @@ fn validate_headers(headers: &HashMap<String, String>) -> Result<(), HTTPError>
                 ));
             }
         }
-        None => return Err(get_bad_request("Invalid header <sec-websocket-key>")),
+        _ => return Err(get_bad_request("Invalid header <sec-websocket-key>")),
     }
 
-    match headers.get("sec-websocket-version") {
-        Some(version) => {
-            if !version.eq("13") {
-                return Err(get_bad_request(
-                    "Version not supported <sec-websocket-version: 13>",
-                ));
-            }
+    let versions: Vec<&str> = headers.get_all("sec-websocket-version").collect();
+    match versions[..] {
+        ["13"] => (),
+        [] => return Err(get_bad_request("Invalid header <sec-websocket-version>")),
+        _ => {
+            return Err(get_upgrade_required(
+                "Version not supported <sec-websocket-version: 13>",
+            ))
         }
-        None => return Err(get_bad_request("Invalid header <sec-websocket-version>")),
     }
 
     Ok(())
 }
 
-fn verify_resource_uri(p0: &str) -> Result<(), HTTPError> {
-    match Url::parse(p0) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(get_bad_request("Malformed resource uri")),
+/// `permessage-deflate` out of `permessage-deflate; client_max_window_bits`
+fn extension_name(extension: &str) -> &str {
+    extension.split(';').next().unwrap_or_default().trim()
+}
+
+/// 431 for a head over the limits, 408 for one too slow to arrive, 400 for anything else
+fn head_error(e: HeadError) -> HTTPError {
+    match e {
+        HeadError::TooLarge(_) | HeadError::TooManyHeaders(_) => {
+            get_http_error(HTTPStatus::Other(431), &e.to_string())
+        }
+        HeadError::TimedOut => get_http_error(HTTPStatus::Other(408), &e.to_string()),
+        _ => get_bad_request(&e.to_string()),
+    }
+}
+
+/// Splits the origin-form `/path?query` clients send, or an absolute-form URL, into the path
+/// and the decoded query parameters
+pub(crate) fn parse_request_target(p0: &str) -> Result<(String, Vec<(String, String)>), HTTPError> {
+    let url = if p0.starts_with('/') {
+        // any authority works, only the path and query are kept
+        Url::parse(&format!("http://localhost{}", p0))
+    } else {
+        Url::parse(p0)
+    };
+
+    match url {
+        Ok(u) if matches!(u.scheme(), "http" | "https" | "ws" | "wss") && u.has_host() => Ok((
+            u.path().to_string(),
+            u.query_pairs()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+        )),
+        _ => Err(get_bad_request("Malformed resource uri")),
     }
 }
 
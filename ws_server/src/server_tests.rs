@@ -1,26 +1,60 @@
 #[cfg(test)]
 mod tests {
-    use crate::errors::HTTPStatus;
-    use crate::server::WSUpgrade;
+    use crate::auth::Accept;
+    use crate::errors::{get_http_error, HTTPStatus};
+    use crate::server::{
+        parse_request_target, SelectExtensions, SubprotocolPolicy, WSServerListener, WSUpgrade,
+    };
+    use crate::test_common::{client, server, upgrade_request};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use ws_client::builder::WSClientBuilder;
+    use ws_core::data_frame_tx::{Agent, DataFrame, FrameType};
+    use ws_core::http_utils::{parse_request, read_head, HeadLimits};
+    use ws_core::WSHandler;
+
+    #[derive(Default)]
+    struct Recorder {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl WSHandler for Recorder {
+        fn who(&self) -> Agent {
+            Agent::Server
+        }
+
+        fn handle_text_msg(&self, msg: String) {
+            self.messages.lock().unwrap().push(msg);
+        }
+
+        fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+    }
+
+    fn protocol_handshake() -> String {
+        upgrade_request("/chat", "Sec-WebSocket-Protocol: v2.chat, v1.chat\r\n")
+    }
 
     #[test]
     fn test_ws_handshake() {
-        let handshake = "GET ws://echo.websocket.org/ HTTP/1.1\n\
-                Host: echo.websocket.org\n\
-                Connection: Upgrade\n\
-                Pragma: no-cache\n\
-                Cache-Control: no-cache\n\
-                Upgrade: websocket\n\
-                Origin: https://websocketking.com\n\
-                Sec-WebSocket-Version: 13\n\
-                Accept-Encoding: gzip, deflate, br, zstd\n\
-                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n\
-                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits";
-
-        let expected_res_handshake = "HTTP/1.1 101 Switching Protocols\n\
-        Upgrade: websocket\n\
-        Connection: Upgrade\n\
-        Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        let handshake = "GET ws://echo.websocket.org/ HTTP/1.1\r\n\
+                Host: echo.websocket.org\r\n\
+                Connection: Upgrade\r\n\
+                Pragma: no-cache\r\n\
+                Cache-Control: no-cache\r\n\
+                Upgrade: websocket\r\n\
+                Origin: https://websocketking.com\r\n\
+                Sec-WebSocket-Version: 13\r\n\
+                Accept-Encoding: gzip, deflate, br, zstd\r\n\
+                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n";
+
+        let expected_res_handshake = "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"
             .to_ascii_lowercase();
 
         let mut ws_server = WSUpgrade::new();
@@ -39,17 +73,17 @@ mod tests {
 
     #[test]
     fn test_ws_invalid_http_version() {
-        let handshake = "GET ws://echo.websocket.org/ HTTP/1.0\n\
-                Host: echo.websocket.org\n\
-                Connection: Upgrade\n\
-                Pragma: no-cache\n\
-                Cache-Control: no-cache\n\
-                Upgrade: websocket\n\
-                Origin: https://websocketking.com\n\
-                Sec-WebSocket-Version: 13\n\
-                Accept-Encoding: gzip, deflate, br, zstd\n\
-                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n\
-                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits";
+        let handshake = "GET ws://echo.websocket.org/ HTTP/1.0\r\n\
+                Host: echo.websocket.org\r\n\
+                Connection: Upgrade\r\n\
+                Pragma: no-cache\r\n\
+                Cache-Control: no-cache\r\n\
+                Upgrade: websocket\r\n\
+                Origin: https://websocketking.com\r\n\
+                Sec-WebSocket-Version: 13\r\n\
+                Accept-Encoding: gzip, deflate, br, zstd\r\n\
+                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n";
 
         let mut ws_server = WSUpgrade::new();
 
@@ -58,17 +92,17 @@ mod tests {
 
     #[test]
     fn test_ws_invalid_key() {
-        let handshake = "GET ws://echo.websocket.org/ HTTP/1.0\n\
-                Host: echo.websocket.org\n\
-                Connection: Upgrade\n\
-                Pragma: no-cache\n\
-                Cache-Control: no-cache\n\
-                Upgrade: websocket\n\
-                Origin: https://websocketking.com\n\
-                Sec-WebSocket-Version: 13\n\
-                Accept-Encoding: gzip, deflate, br, zstd\n\
-                Sec-WebSocket-Key: YWJj\n\
-                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits";
+        let handshake = "GET ws://echo.websocket.org/ HTTP/1.0\r\n\
+                Host: echo.websocket.org\r\n\
+                Connection: Upgrade\r\n\
+                Pragma: no-cache\r\n\
+                Cache-Control: no-cache\r\n\
+                Upgrade: websocket\r\n\
+                Origin: https://websocketking.com\r\n\
+                Sec-WebSocket-Version: 13\r\n\
+                Accept-Encoding: gzip, deflate, br, zstd\r\n\
+                Sec-WebSocket-Key: YWJj\r\n\
+                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n";
 
         let mut ws_server = WSUpgrade::new();
 
@@ -77,16 +111,16 @@ mod tests {
 
     #[test]
     fn test_ws_missing_headers() {
-        let handshake = "GET ws://echo.websocket.org/ HTTP/1.0\n\
-                Host: echo.websocket.org\n\
-                Pragma: no-cache\n\
-                Cache-Control: no-cache\n\
-                Upgrade: websocket\n\
-                Origin: https://websocketking.com\n\
-                Sec-WebSocket-Version: 13\n\
-                Accept-Encoding: gzip, deflate, br, zstd\n\
-                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n\
-                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits";
+        let handshake = "GET ws://echo.websocket.org/ HTTP/1.0\r\n\
+                Host: echo.websocket.org\r\n\
+                Pragma: no-cache\r\n\
+                Cache-Control: no-cache\r\n\
+                Upgrade: websocket\r\n\
+                Origin: https://websocketking.com\r\n\
+                Sec-WebSocket-Version: 13\r\n\
+                Accept-Encoding: gzip, deflate, br, zstd\r\n\
+                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n";
 
         let mut ws_server = WSUpgrade::new();
 
@@ -95,23 +129,225 @@ mod tests {
 
     #[test]
     fn test_ws_wrong_http_method() {
-        let handshake = "POST ws://echo.websocket.org/ HTTP/1.1\n\
-                Host: echo.websocket.org\n\
-                Connection: Upgrade\n\
-                Pragma: no-cache\n\
-                Cache-Control: no-cache\n\
-                Upgrade: websocket\n\
-                Origin: https://websocketking.com\n\
-                Sec-WebSocket-Version: 13\n\
-                Accept-Encoding: gzip, deflate, br, zstd\n\
-                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n\
-                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits";
+        let handshake = "POST ws://echo.websocket.org/ HTTP/1.1\r\n\
+                Host: echo.websocket.org\r\n\
+                Connection: Upgrade\r\n\
+                Pragma: no-cache\r\n\
+                Cache-Control: no-cache\r\n\
+                Upgrade: websocket\r\n\
+                Origin: https://websocketking.com\r\n\
+                Sec-WebSocket-Version: 13\r\n\
+                Accept-Encoding: gzip, deflate, br, zstd\r\n\
+                Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n";
 
         let mut ws_server = WSUpgrade::new();
 
         validate_400_error(handshake, &mut ws_server, 405, HTTPStatus::MethodNotAllowed);
     }
 
+    #[test]
+    fn test_ws_connection_token_list() {
+        // as sent by Firefox, with the protocols split over two fields
+        let handshake = protocol_handshake()
+            .replace("Connection: Upgrade", "Connection: keep-alive, Upgrade")
+            .replace("\r\n\r\n", "\r\nSec-WebSocket-Protocol: v3.chat\r\n\r\n");
+
+        let mut ws_upgrade = WSUpgrade::new();
+        ws_upgrade.parse_handshake(handshake.into_bytes()).unwrap();
+        assert_eq!(
+            ws_upgrade.offered_protocols(),
+            ["v2.chat", "v1.chat", "v3.chat"]
+        );
+    }
+
+    #[test]
+    fn test_ws_malformed_heads() {
+        let bare_lf = protocol_handshake().replace("\r\n", "\n");
+        let e = WSUpgrade::new()
+            .parse_handshake(bare_lf.into_bytes())
+            .unwrap_err();
+        assert_eq!(e.code, 400);
+        assert_eq!(e.message, "Line 1 does not end with CRLF");
+
+        let no_colon = protocol_handshake().replace("Upgrade: websocket", "Upgrade websocket");
+        let e = WSUpgrade::new()
+            .parse_handshake(no_colon.into_bytes())
+            .unwrap_err();
+        assert_eq!(e.message, "Invalid header on line 4: Missing colon");
+
+        let two_keys = protocol_handshake().replace(
+            "Upgrade: websocket",
+            "Upgrade: websocket\r\nSec-WebSocket-Key: AAAAAAAAAAAAAAAAAAAAAA==",
+        );
+        assert!(WSUpgrade::new()
+            .parse_handshake(two_keys.into_bytes())
+            .is_err());
+
+        let cookie = format!("Cookie: {}\r\n\r\n", "x".repeat(20 * 1024));
+        let too_large = protocol_handshake().replace("\r\n\r\n", &format!("\r\n{}", cookie));
+        let e = WSUpgrade::new()
+            .parse_handshake(too_large.into_bytes())
+            .unwrap_err();
+        assert_eq!(e.code, 431);
+    }
+
+    #[test]
+    fn test_select_protocol_by_preference() {
+        let mut ws_upgrade = WSUpgrade::new();
+        ws_upgrade
+            .parse_handshake(protocol_handshake().as_bytes().to_vec())
+            .unwrap();
+        assert_eq!(ws_upgrade.offered_protocols(), ["v2.chat", "v1.chat"]);
+
+        // the server's order wins over the client's
+        let preference = vec!["v1.chat".to_string(), "v2.chat".to_string()];
+        ws_upgrade.select_protocol(&SubprotocolPolicy::Preference(preference));
+        assert_eq!(ws_upgrade.protocol(), Some("v1.chat"));
+
+        let res = String::from_utf8(ws_upgrade.create_handshake()).unwrap();
+        assert!(res.ends_with("\r\nSec-WebSocket-Protocol: v1.chat\r\n\r\n"));
+
+        ws_upgrade.select_protocol(&SubprotocolPolicy::Preference(vec!["mqtt".to_string()]));
+        assert_eq!(ws_upgrade.protocol(), None);
+        let res = String::from_utf8(ws_upgrade.create_handshake()).unwrap();
+        assert!(!res.contains("Sec-WebSocket-Protocol"));
+    }
+
+    #[test]
+    fn test_select_protocol_by_callback() {
+        let mut ws_upgrade = WSUpgrade::new();
+        ws_upgrade
+            .parse_handshake(protocol_handshake().as_bytes().to_vec())
+            .unwrap();
+
+        let last = SubprotocolPolicy::Select(Arc::new(|offered| offered.last().cloned()));
+        ws_upgrade.select_protocol(&last);
+        assert_eq!(ws_upgrade.protocol(), Some("v1.chat"));
+
+        // a protocol the client did not offer is never sent back
+        let other = SubprotocolPolicy::Select(Arc::new(|_| Some("mqtt".to_string())));
+        ws_upgrade.select_protocol(&other);
+        assert_eq!(ws_upgrade.protocol(), None);
+    }
+
+    #[test]
+    fn test_upgrade_info_on_both_sides() {
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_subprotocols(SubprotocolPolicy::Preference(vec![
+            "v2.chat".to_string(),
+            "v1.chat".to_string(),
+        ]));
+        listener.set_extensions(&["x-trace"], |offered| offered.to_vec());
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        let client = WSClientBuilder::new(&format!("ws://{}/chat?room=42", addr))
+            .protocol("v1.chat")
+            .protocol("v2.chat")
+            .extension("x-trace")
+            .connect(client())
+            .unwrap();
+
+        assert_eq!(client.protocol(), Some("v2.chat"));
+        assert_eq!(client.extensions(), ["x-trace"]);
+        let info = server.join().unwrap();
+        assert_eq!(info.protocol, Some("v2.chat".to_string()));
+        assert_eq!(info.extensions, ["x-trace"]);
+        assert_eq!(info.path, "/chat");
+        assert_eq!(info.query_param("room"), Some("42"));
+    }
+
+    #[test]
+    fn test_error_response() {
+        let e = get_http_error(HTTPStatus::TooManyRequests, "Slow down")
+            .with_header("Retry-After", "30");
+        assert_eq!(e.code, 429);
+
+        let res = String::from_utf8(e.to_response()).unwrap();
+        assert_eq!(
+            res,
+            "HTTP/1.1 429 Too Many Requests\r\n\
+            Retry-After: 30\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            Content-Length: 9\r\n\
+            Connection: close\r\n\r\n\
+            Slow down"
+        );
+    }
+
+    #[test]
+    fn test_ws_unsupported_version() {
+        let handshake = protocol_handshake().replace("Version: 13", "Version: 8");
+
+        let e = WSUpgrade::new()
+            .parse_handshake(handshake.into_bytes())
+            .unwrap_err();
+        assert_eq!(e.status, HTTPStatus::UpgradeRequired);
+
+        let res = String::from_utf8(e.to_response()).unwrap();
+        assert!(res.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+        assert!(res.contains("\r\nSec-WebSocket-Version: 13\r\n"));
+    }
+
+    #[test]
+    fn test_rejected_upgrade_gets_response() {
+        let listener = WSServerListener::init(0, server()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"POST /chat HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+
+        assert!(res.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(res.contains("\r\nAllow: GET\r\n"));
+        assert!(res.ends_with("\r\n\r\nMethod Not Allowed"));
+
+        // the listener goes on with the next client
+        let _client = WSClientBuilder::new(&format!("ws://{}/chat", addr))
+            .connect(client())
+            .unwrap();
+        assert_eq!(server.join().unwrap().path, "/chat");
+    }
+
+    #[test]
+    fn test_request_targets() {
+        let (path, query) = parse_request_target("/chat?room=42&token=a%2Bb%20c&room=7").unwrap();
+        assert_eq!(path, "/chat");
+        assert_eq!(
+            query,
+            [
+                ("room".to_string(), "42".to_string()),
+                ("token".to_string(), "a+b c".to_string()),
+                ("room".to_string(), "7".to_string()),
+            ]
+        );
+
+        let (path, query) = parse_request_target("ws://echo.websocket.org/feeds/a?x=1").unwrap();
+        assert_eq!(path, "/feeds/a");
+        assert_eq!(query, [("x".to_string(), "1".to_string())]);
+
+        let (path, _) = parse_request_target("//double/slash").unwrap();
+        assert_eq!(path, "//double/slash");
+
+        // normalized, not as sent
+        let path = |target| parse_request_target(target).unwrap().0;
+        assert_eq!(path("/a/../b"), "/b");
+        assert_eq!(path("/a/./%2e%2E/b/"), "/b/");
+        assert_eq!(path("/../b"), "/b");
+        assert_eq!(path("/a/..%2Fb"), "/a/..%2Fb");
+        assert_eq!(path("/a b"), "/a%20b");
+
+        assert!(parse_request_target("*").is_err());
+        assert!(parse_request_target("echo.websocket.org:80").is_err());
+        assert!(parse_request_target("mailto:someone@example.com").is_err());
+    }
+
     fn validate_400_error(
         handshake: &str,
         ws_server: &mut WSUpgrade,
@@ -126,4 +362,245 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_split_request_and_early_frames() {
+        let listener = WSServerListener::init(0, Recorder::default()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut client = listener.accept().unwrap();
+            client.ws_stream.read().unwrap();
+            let messages = client.ws_stream.handler.messages.lock().unwrap().clone();
+            messages
+        });
+
+        // a browser-sized request arriving in pieces, the first frame in the same segment as
+        // the end of the head. Heads are read 1 KiB at a time, so it takes several reads
+        // however the pieces arrive.
+        let cookie = format!("Cookie: session={}\r\n", "x".repeat(3000));
+        let request = protocol_handshake().replace("Host:", &format!("{}Host:", cookie));
+        let (start, end) = request.split_at(1500);
+        let mut last = end.as_bytes().to_vec();
+        last.extend(Vec::from(
+            DataFrame::build(b"early", FrameType::Text, Agent::Client).unwrap(),
+        ));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(start.as_bytes()).unwrap();
+        stream.write_all(&last).unwrap();
+
+        let mut res = [0u8; 512];
+        let n = stream.read(&mut res).unwrap();
+        assert!(res[..n].starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+        assert_eq!(server.join().unwrap(), ["early"]);
+    }
+
+    #[test]
+    fn test_slow_and_oversized_requests() {
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_handshake_timeout(Duration::from_millis(200));
+        listener.set_max_header_size(1024);
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        // never finishes its head
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&protocol_handshake().as_bytes()[..50])
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+        // exactly the limit without the blank line, so nothing is left unread
+        let mut large = protocol_handshake().trim_end().to_string();
+        large.push_str("\r\nCookie: ");
+        large.push_str(&"x".repeat(1024 - large.len()));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(large.as_bytes()).unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        // neither kept the listener from upgrading the next client
+        let _client = WSClientBuilder::new(&format!("ws://{}/chat", addr))
+            .connect(client())
+            .unwrap();
+        assert_eq!(server.join().unwrap().path, "/chat");
+    }
+
+    #[test]
+    fn test_stalled_client_does_not_hold_up_others() {
+        let listener = WSServerListener::init(0, server()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        // connected, so accepted, first, then sends part of its head and nothing more for the
+        // whole default handshake timeout
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled
+            .write_all(&protocol_handshake().as_bytes()[..50])
+            .unwrap();
+
+        let started = Instant::now();
+        let _client = WSClientBuilder::new(&format!("ws://{}/chat", addr))
+            .connect(client())
+            .unwrap();
+        assert_eq!(server.join().unwrap().path, "/chat");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        drop(stalled);
+    }
+
+    #[test]
+    fn test_frame_and_message_limits() {
+        let mut listener = WSServerListener::init(0, Recorder::default()).unwrap();
+        listener.set_max_frame_size(16);
+        listener.set_max_message_size(16);
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.read());
+
+        let mut client = WSClientBuilder::new(&format!("ws://{}/chat", addr))
+            .connect(client())
+            .unwrap();
+        client
+            .ws_stream
+            .write(&[b'a'; 100], FrameType::Text)
+            .unwrap();
+        assert!(server.join().unwrap().unwrap_err().contains("1009"));
+    }
+
+    #[test]
+    fn test_handshakes_beyond_the_limit() {
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_max_handshakes(1);
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || listener.accept().unwrap().ws_stream.info().clone());
+
+        let mut first = TcpStream::connect(addr).unwrap();
+        first
+            .write_all(&protocol_handshake().as_bytes()[..50])
+            .unwrap();
+
+        // the first one is still handshaking
+        let mut second = TcpStream::connect(addr).unwrap();
+        let mut res = String::new();
+        second.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(res.contains("Retry-After: 1\r\n"));
+
+        first
+            .write_all(&protocol_handshake().as_bytes()[50..])
+            .unwrap();
+        assert_eq!(server.join().unwrap().path, "/chat");
+    }
+
+    #[test]
+    fn test_negotiated_response() {
+        let handshake = protocol_handshake().replace(
+            "\r\n\r\n",
+            "\r\nSec-WebSocket-Extensions: permessage-deflate; client_max_window_bits, x-trace\r\n\r\n",
+        );
+        let mut ws_upgrade = WSUpgrade::new();
+        ws_upgrade.parse_handshake(handshake.into_bytes()).unwrap();
+        assert_eq!(
+            ws_upgrade.offered_extensions(),
+            ["permessage-deflate; client_max_window_bits", "x-trace"]
+        );
+
+        ws_upgrade.select_protocol(&SubprotocolPolicy::Preference(vec!["v1.chat".to_string()]));
+        let select: SelectExtensions = Arc::new(|offered| {
+            // only the allowed ones are offered to choose from
+            assert_eq!(offered, ["x-trace"]);
+            vec![
+                // would set RSV1 on frames that are read as they are
+                "permessage-deflate".to_string(),
+                "X-Trace; id=1".to_string(),
+                "x-unknown".to_string(),
+                "x-trace".to_string(),
+            ]
+        });
+        let allowed = ["x-trace".to_string(), "x-unknown".to_string()];
+        ws_upgrade.select_extensions(&allowed, &select);
+        assert_eq!(ws_upgrade.extensions(), ["X-Trace; id=1"]);
+        ws_upgrade.add_response_header("Set-Cookie", "seen=1");
+
+        assert_eq!(
+            String::from_utf8(ws_upgrade.create_handshake()).unwrap(),
+            "HTTP/1.1 101 Switching Protocols\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+            Sec-WebSocket-Protocol: v1.chat\r\n\
+            Sec-WebSocket-Extensions: X-Trace; id=1\r\n\
+            Set-Cookie: seen=1\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_third_party_client() {
+        use tungstenite::client::IntoClientRequest;
+        use tungstenite::Message;
+
+        let mut listener = WSServerListener::init(0, Recorder::default()).unwrap();
+        listener.set_subprotocols(SubprotocolPolicy::Preference(vec!["v1.chat".to_string()]));
+        listener.set_auth(|_, _| Ok(Accept::new().header("X-Served-By", "ws_server")));
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut client = listener.accept().unwrap();
+            client.ws_stream.write(b"hello", FrameType::Text).unwrap();
+            client.ws_stream.read().unwrap();
+            let messages = client.ws_stream.handler.messages.lock().unwrap().clone();
+            messages
+        });
+
+        let mut request = format!("ws://{}/chat", addr).into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", "v1.chat".parse().unwrap());
+        let stream = TcpStream::connect(addr).unwrap();
+        let (mut socket, response) = tungstenite::client(request, stream).unwrap();
+
+        assert_eq!(response.status(), 101);
+        assert_eq!(response.headers()["sec-websocket-protocol"], "v1.chat");
+        assert_eq!(response.headers()["x-served-by"], "ws_server");
+        assert_eq!(socket.read().unwrap(), Message::text("hello"));
+        socket.send(Message::text("hi")).unwrap();
+        assert_eq!(server.join().unwrap(), ["hi"]);
+    }
+
+    #[test]
+    fn test_embedded_upgrade() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // an HTTP server of its own, handing /ws over once it has parsed the request
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let socket = stream.try_clone().unwrap();
+            let (head, rest) = read_head(
+                &mut stream,
+                &socket,
+                &HeadLimits::default(),
+                None,
+                parse_request,
+            )
+            .unwrap();
+            assert_eq!(head.target, "/ws");
+
+            let ws_upgrade =
+                WSUpgrade::from_parts(&head.method, &head.target, head.headers).unwrap();
+            stream.write_all(&ws_upgrade.create_handshake()).unwrap();
+            let mut ws_stream = ws_upgrade.into_stream(stream, rest, Arc::new(Recorder::default()));
+            assert_eq!(ws_stream.info().path, "/ws");
+            ws_stream.read().unwrap();
+            let messages = ws_stream.handler.messages.lock().unwrap().clone();
+            messages
+        });
+
+        let mut client = WSClientBuilder::new(&format!("ws://{}/ws", addr))
+            .connect(client())
+            .unwrap();
+        client.ws_stream.write(b"hi", FrameType::Text).unwrap();
+        assert_eq!(server.join().unwrap(), ["hi"]);
+    }
 }
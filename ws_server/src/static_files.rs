@@ -0,0 +1,220 @@
+use percent_encoding::percent_decode_str;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::endpoints::{HttpRequest, HttpResponse};
+
+/// Files of a directory served over plain HTTP, meant for development, e.g. the build of the
+/// page that opens the socket. See `Endpoints::static_dir`.
+///
+/// Each file is read whole into memory before it is sent. That happens on the thread of the
+/// connection's handshake, so a large file or a slow disk holds up that client only, not the
+/// listener.
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Answers `req` with the file at `rel`, a percent-encoded path relative to the root.
+    /// Directories are answered with their `index.html`, anything resolving outside the root
+    /// with 403.
+    pub fn serve(&self, req: &HttpRequest, rel: &str) -> HttpResponse {
+        if req.method != "GET" && req.method != "HEAD" {
+            return HttpResponse::text("Method Not Allowed")
+                .status(405)
+                .header("Allow", "GET, HEAD");
+        }
+
+        let path = match self.resolve(rel) {
+            Ok(p) => p,
+            Err(status) => return HttpResponse::text(reason(status)).status(status),
+        };
+
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if let (Some(modified), Some(since)) = (
+            modified,
+            req.headers
+                .get("if-modified-since")
+                .and_then(parse_http_date),
+        ) {
+            if truncate(modified) <= since {
+                return HttpResponse::new(304);
+            }
+        }
+
+        let body = match fs::read(&path) {
+            Ok(b) => b,
+            Err(_) => return HttpResponse::text(reason(404)).status(404),
+        };
+
+        let mut res = HttpResponse::new(200)
+            .header("Content-Type", content_type(&path))
+            .body(body);
+        if let Some(modified) = modified {
+            res = res.header("Last-Modified", &http_date(modified));
+        }
+        res
+    }
+
+    /// The file `rel` stands for, or the status to answer with
+    fn resolve(&self, rel: &str) -> Result<PathBuf, u16> {
+        let decoded = match percent_decode_str(rel).decode_utf8() {
+            Ok(d) => d.to_string(),
+            Err(_) => return Err(404),
+        };
+
+        let mut path = self.root.clone();
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => (),
+                ".." => return Err(403),
+                s if s.contains(['\\', '\0', ':']) => return Err(403),
+                s => path.push(s),
+            }
+        }
+        if path.is_dir() {
+            path.push("index.html");
+        }
+
+        // a symlink could still lead out of the root
+        let root = match self.root.canonicalize() {
+            Ok(r) => r,
+            Err(_) => return Err(404),
+        };
+        match path.canonicalize() {
+            Ok(p) if !p.starts_with(&root) => Err(403),
+            Ok(p) if p.is_file() => Ok(p),
+            _ => Err(404),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        403 => "Forbidden",
+        _ => "Not Found",
+    }
+}
+
+/// By extension, `application/octet-stream` for anything unknown
+pub fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// IMF-fixdate like `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Reads an IMF-fixdate, the obsolete formats are not accepted
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = date.split(' ').collect();
+    if parts.len() != 6 || parts[5] != "GMT" || !DAYS.iter().any(|d| parts[0] == format!("{},", d))
+    {
+        return None;
+    }
+
+    let day: u32 = parts[1].parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == parts[2])? as u32 + 1;
+    let year: i64 = parts[3].parse().ok()?;
+    let time: Vec<u64> = parts[4]
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if time.len() != 3 || time[0] > 23 || time[1] > 59 || time[2] > 60 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    let secs = days as u64 * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// `Last-Modified` only has whole seconds
+fn truncate(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Year, month and day of the days since 1970-01-01, from Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of `civil_from_days`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
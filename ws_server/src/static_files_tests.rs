@@ -0,0 +1,189 @@
+#[cfg(test)]
+mod tests {
+    use crate::endpoints::{Endpoints, HttpRequest, HttpResponse};
+    use crate::server::WSServerListener;
+    use crate::static_files::{content_type, http_date, parse_http_date, StaticFiles};
+    use crate::test_common::server;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+    use ws_core::http_utils::Headers;
+
+    /// `<tmp>/<name>/site` with a page, a script and a sub directory, next to a file that must
+    /// not be served
+    fn site(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("ws_static_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("site");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("index.html"), "<h1>app</h1>").unwrap();
+        fs::write(root.join("app.js"), "connect()").unwrap();
+        fs::write(root.join("docs/index.html"), "docs").unwrap();
+        fs::write(base.join("secret.txt"), "secret").unwrap();
+        root
+    }
+
+    fn get(files: &StaticFiles, path: &str, headers: &[(&str, &str)]) -> HttpResponse {
+        let mut req = HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: vec![],
+            headers: Headers::new(),
+            peer_addr: None,
+        };
+        for (name, value) in headers {
+            req.headers.append(name, value);
+        }
+        files.serve(&req, path)
+    }
+
+    fn header<'a>(res: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        res.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2028 23:59:59 GMT").map(http_date),
+            Some("Tue, 29 Feb 2028 23:59:59 GMT".to_string())
+        );
+
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nox 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+    }
+
+    #[test]
+    fn test_content_types() {
+        assert_eq!(
+            content_type(Path::new("a/index.HTML")),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            content_type(Path::new("app.mjs")),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(content_type(Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(content_type(Path::new("app.wasm")), "application/wasm");
+        assert_eq!(
+            content_type(Path::new("LICENSE")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_serve_files() {
+        let files = StaticFiles::new(site("serve"));
+
+        let res = get(&files, "/", &[]);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.body, b"<h1>app</h1>");
+        assert_eq!(
+            header(&res, "content-type"),
+            Some("text/html; charset=utf-8")
+        );
+
+        let res = get(&files, "/app.js", &[]);
+        assert_eq!(res.body, b"connect()");
+        assert_eq!(
+            header(&res, "content-type"),
+            Some("text/javascript; charset=utf-8")
+        );
+
+        assert_eq!(get(&files, "/docs", &[]).body, b"docs");
+        assert_eq!(get(&files, "/docs/", &[]).body, b"docs");
+        assert_eq!(get(&files, "/missing.js", &[]).status, 404);
+
+        let mut post = HttpRequest {
+            method: "POST".to_string(),
+            path: "/".to_string(),
+            query: vec![],
+            headers: Headers::new(),
+            peer_addr: None,
+        };
+        assert_eq!(files.serve(&post, "/").status, 405);
+        post.method = "HEAD".to_string();
+        assert_eq!(files.serve(&post, "/").status, 200);
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let files = StaticFiles::new(site("modified"));
+
+        let res = get(&files, "/app.js", &[]);
+        let modified = header(&res, "last-modified").unwrap().to_string();
+
+        let res = get(&files, "/app.js", &[("If-Modified-Since", &modified)]);
+        assert_eq!(res.status, 304);
+        assert!(res.body.is_empty());
+
+        let old = get(
+            &files,
+            "/app.js",
+            &[("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")],
+        );
+        assert_eq!(old.status, 200);
+        let invalid = get(&files, "/app.js", &[("If-Modified-Since", "yesterday")]);
+        assert_eq!(invalid.status, 200);
+    }
+
+    #[test]
+    fn test_path_traversal() {
+        let root = site("traversal");
+        let files = StaticFiles::new(&root);
+
+        assert_eq!(get(&files, "/../secret.txt", &[]).status, 403);
+        assert_eq!(get(&files, "/%2e%2e/secret.txt", &[]).status, 403);
+        assert_eq!(
+            get(&files, "/docs/%2E%2E/%2E%2E/secret.txt", &[]).status,
+            403
+        );
+        assert_eq!(get(&files, "/..%2fsecret.txt", &[]).status, 403);
+        assert_eq!(get(&files, "/..%5csecret.txt", &[]).status, 403);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("../secret.txt"), root.join("link.txt")).unwrap();
+            assert_eq!(get(&files, "/link.txt", &[]).status, 403);
+        }
+    }
+
+    #[test]
+    fn test_static_dir_on_listener() {
+        let root = site("listener");
+        let mut listener = WSServerListener::init(0, server()).unwrap();
+        listener.set_endpoints(
+            Endpoints::new()
+                .health("/healthz")
+                .static_dir("/app", &root),
+        );
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || listener.accept());
+
+        let get = |target: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let req = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target);
+            stream.write_all(req.as_bytes()).unwrap();
+            let mut res = String::new();
+            stream.read_to_string(&mut res).unwrap();
+            res
+        };
+
+        assert!(get("/app").ends_with("\r\n\r\n<h1>app</h1>"));
+        assert!(get("/app/app.js?v=2").ends_with("\r\n\r\nconnect()"));
+        // dot segments are already resolved with the request-target, encoded slashes are not
+        assert!(get("/app/..%2f..%2fsecret.txt").starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(get("/healthz").ends_with("\r\n\r\nok"));
+    }
+}
@@ -0,0 +1,44 @@
+//! Handler and upgrade request shared by the tests
+
+use ws_core::data_frame_tx::Agent;
+use ws_core::WSHandler;
+
+/// Ignores every message
+pub(crate) struct TestHandler {
+    agent: Agent,
+}
+
+impl WSHandler for TestHandler {
+    fn who(&self) -> Agent {
+        self.agent
+    }
+
+    fn handle_text_msg(&self, _msg: String) {}
+
+    fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+}
+
+pub(crate) fn server() -> TestHandler {
+    TestHandler {
+        agent: Agent::Server,
+    }
+}
+
+pub(crate) fn client() -> TestHandler {
+    TestHandler {
+        agent: Agent::Client,
+    }
+}
+
+/// Upgrade request for `target` with the `extra` header lines, each ending in `\r\n`
+pub(crate) fn upgrade_request(target: &str, extra: &str) -> String {
+    format!(
+        "GET {} HTTP/1.1\r\n\
+        Host: 127.0.0.1\r\n\
+        Connection: Upgrade\r\n\
+        Upgrade: websocket\r\n\
+        Sec-WebSocket-Version: 13\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{}\r\n",
+        target, extra
+    )
+}
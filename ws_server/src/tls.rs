@@ -0,0 +1,153 @@
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::errors::HTTPError;
+use crate::server::{handshake, UpgradePolicy, WSUpgrade};
+use ws_core::conn_info::{ConnectionInfo, PeerIdentity};
+use ws_core::http_utils::apply_deadline;
+use ws_core::pem::{certs_from_pem, key_from_pem, roots_from_pem};
+use ws_core::rustls::crypto::ring;
+use ws_core::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use ws_core::rustls::server::WebPkiClientVerifier;
+use ws_core::rustls::{RootCertStore, ServerConfig, ServerConnection, Stream};
+use ws_core::{WSHandler, WSStream};
+
+/// Server config presenting `cert_chain`, leaf certificate first, signed with `key`
+pub fn server_config(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<ServerConfig>, String> {
+    let config = match ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+    {
+        Ok(b) => b.with_no_client_auth().with_single_cert(cert_chain, key),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    match config {
+        Ok(c) => Ok(Arc::new(c)),
+        Err(e) => Err(format!("Invalid certificate or key: {}", e)),
+    }
+}
+
+/// Server config for mutual TLS, clients have to present a certificate issued by one of
+/// `client_roots`. The verified certificate is exposed as `ConnectionInfo::peer_identity`.
+pub fn server_config_mtls(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_roots: RootCertStore,
+) -> Result<Arc<ServerConfig>, String> {
+    let provider = Arc::new(ring::default_provider());
+    let verifier =
+        match WebPkiClientVerifier::builder_with_provider(Arc::new(client_roots), provider.clone())
+            .build()
+        {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Invalid client CA: {}", e)),
+        };
+
+    let config =
+        match ServerConfig::builder_with_provider(provider).with_safe_default_protocol_versions() {
+            Ok(b) => b
+                .with_client_cert_verifier(verifier)
+                .with_single_cert(cert_chain, key),
+            Err(e) => return Err(e.to_string()),
+        };
+
+    match config {
+        Ok(c) => Ok(Arc::new(c)),
+        Err(e) => Err(format!("Invalid certificate or key: {}", e)),
+    }
+}
+
+/// Same as `server_config` with the chain and the key read from PEM
+pub fn server_config_from_pem(
+    cert_pem: &[u8],
+    key_pem: &[u8],
+) -> Result<Arc<ServerConfig>, String> {
+    server_config(certs_from_pem(cert_pem)?, key_from_pem(key_pem)?)
+}
+
+/// Same as `server_config_mtls` with the chain, the key and the client CA certificates read
+/// from PEM
+pub fn server_config_mtls_from_pem(
+    cert_pem: &[u8],
+    key_pem: &[u8],
+    client_ca_pem: &[u8],
+) -> Result<Arc<ServerConfig>, String> {
+    server_config_mtls(
+        certs_from_pem(cert_pem)?,
+        key_from_pem(key_pem)?,
+        roots_from_pem(client_ca_pem)?,
+    )
+}
+
+/// Runs the TLS handshake and then the websocket upgrade inside the session, both before
+/// `deadline`
+pub(crate) fn accept<H, F>(
+    mut stream: TcpStream,
+    config: Arc<ServerConfig>,
+    policy: &UpgradePolicy,
+    deadline: Option<Instant>,
+    mut info: ConnectionInfo,
+    route: F,
+) -> Result<Option<WSStream<H>>, String>
+where
+    H: WSHandler,
+    F: FnOnce(&mut WSUpgrade, ConnectionInfo) -> Result<(Arc<H>, ConnectionInfo), HTTPError>,
+{
+    let mut conn = match ServerConnection::new(config) {
+        Ok(c) => c,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    // finished first so the client certificate is known when routing
+    while conn.is_handshaking() {
+        if let Err(e) =
+            apply_deadline(&stream, deadline).and_then(|_| conn.complete_io(&mut stream))
+        {
+            return match e.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                    Err("TLS handshake timed out".to_string())
+                }
+                _ => Err(e.to_string()),
+            };
+        }
+    }
+    // rustls verified the certificate, one that cannot be read must not pass as no certificate
+    if let Some(cert) = conn.peer_certificates().and_then(|certs| certs.first()) {
+        match PeerIdentity::from_der(cert) {
+            Ok(identity) => info.peer_identity = Some(identity),
+            Err(e) => return Err(format!("Client certificate not readable: {}", e)),
+        }
+    }
+
+    let socket = match stream.try_clone() {
+        Ok(s) => s,
+        Err(e) => return Err(e.to_string()),
+    };
+    let upgraded = handshake(
+        &mut Stream::new(&mut conn, &mut stream),
+        &socket,
+        policy,
+        deadline,
+        |u| route(u, info),
+    )?;
+    let (_, (handler, info), rest) = match upgraded {
+        Some(u) => u,
+        None => {
+            // the response is flushed but close_notify still has to go out
+            conn.send_close_notify();
+            let _ = conn.complete_io(&mut stream);
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(
+        WSStream::new_tls(stream, conn.into(), handler)?
+            .with_info(info)
+            .with_buffered(rest),
+    ))
+}
@@ -0,0 +1,240 @@
+#[cfg(test)]
+mod tests {
+    use crate::server::WSServerListener;
+    use crate::tls::{server_config_from_pem, server_config_mtls_from_pem};
+    use rcgen::{
+        generate_simple_self_signed, BasicConstraints, Certificate, CertificateParams,
+        CertifiedKey, DnType, IsCa, KeyPair,
+    };
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+    use ws_client::client::WSClient;
+    use ws_client::tls::{client_config_from_pem, client_config_with_cert_from_pem};
+    use ws_core::conn_info::ConnectionInfo;
+    use ws_core::data_frame_tx::{Agent, FrameType};
+    use ws_core::WSHandler;
+
+    struct TestHandler {
+        agent: Agent,
+        msgs: Mutex<Sender<String>>,
+        opened: Mutex<Option<Sender<ConnectionInfo>>>,
+    }
+
+    impl WSHandler for TestHandler {
+        fn who(&self) -> Agent {
+            self.agent
+        }
+
+        fn handle_text_msg(&self, msg: String) {
+            self.msgs.lock().unwrap().send(msg).unwrap();
+        }
+
+        fn handle_bin_msg(&self, _msg: Vec<u8>) {}
+
+        fn on_open(&self, info: &ConnectionInfo) {
+            if let Some(tx) = self.opened.lock().unwrap().as_ref() {
+                tx.send(info.clone()).unwrap();
+            }
+        }
+    }
+
+    fn handler(agent: Agent) -> (TestHandler, Receiver<String>) {
+        let (tx, rx) = channel();
+        (
+            TestHandler {
+                agent,
+                msgs: Mutex::new(tx),
+                opened: Mutex::new(None),
+            },
+            rx,
+        )
+    }
+
+    fn tls_server() -> (
+        WSServerListener<TestHandler>,
+        Receiver<String>,
+        CertifiedKey,
+    ) {
+        let cert = generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let config = server_config_from_pem(
+            cert.cert.pem().as_bytes(),
+            cert.key_pair.serialize_pem().as_bytes(),
+        )
+        .unwrap();
+
+        let (server_handler, server_rx) = handler(Agent::Server);
+        let mut server = WSServerListener::init(0, server_handler).unwrap();
+        server.set_tls(config);
+        (server, server_rx, cert)
+    }
+
+    #[test]
+    fn test_wss_echo() {
+        let (server, server_rx, cert) = tls_server();
+        let url = format!("wss://127.0.0.1:{}/", server.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            let mut client = server.accept().unwrap();
+            let mut reader = client.ws_stream.clone();
+            thread::spawn(move || while reader.read().is_ok() {});
+
+            let msg = server_rx.recv().unwrap();
+            client
+                .ws_stream
+                .write(format!("echo {}", msg).as_bytes(), FrameType::Text)
+                .unwrap();
+        });
+
+        let (client_handler, client_rx) = handler(Agent::Client);
+        let config = client_config_from_pem(cert.cert.pem().as_bytes()).unwrap();
+        let mut client = WSClient::connect_tls(&url, client_handler, config).unwrap();
+        client
+            .ws_stream
+            .write("hello".as_bytes(), FrameType::Text)
+            .unwrap();
+
+        let mut reader = client.ws_stream.clone();
+        thread::spawn(move || while reader.read().is_ok() {});
+        assert_eq!(
+            client_rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            "echo hello"
+        );
+    }
+
+    #[test]
+    fn test_untrusted_certificate_is_rejected() {
+        let (server, _server_rx, _cert) = tls_server();
+        let url = format!("wss://127.0.0.1:{}/", server.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            let _ = server.accept();
+        });
+
+        let (client_handler, _client_rx) = handler(Agent::Client);
+        assert!(WSClient::connect(&url, client_handler).is_err());
+    }
+
+    fn issue(params: CertificateParams, ca: Option<(&Certificate, &KeyPair)>) -> CertifiedKey {
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = match ca {
+            Some((ca_cert, ca_key)) => params.signed_by(&key_pair, ca_cert, ca_key).unwrap(),
+            None => params.self_signed(&key_pair).unwrap(),
+        };
+        CertifiedKey { cert, key_pair }
+    }
+
+    fn mtls_server() -> (
+        WSServerListener<TestHandler>,
+        Receiver<ConnectionInfo>,
+        CertifiedKey,
+        CertifiedKey,
+    ) {
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "Mesh CA");
+        let ca = issue(ca_params, None);
+
+        let server_cert = issue(
+            CertificateParams::new(vec!["127.0.0.1".to_string()]).unwrap(),
+            Some((&ca.cert, &ca.key_pair)),
+        );
+        let config = server_config_mtls_from_pem(
+            server_cert.cert.pem().as_bytes(),
+            server_cert.key_pair.serialize_pem().as_bytes(),
+            ca.cert.pem().as_bytes(),
+        )
+        .unwrap();
+
+        let mut client_params =
+            CertificateParams::new(vec!["billing.mesh.internal".to_string()]).unwrap();
+        client_params
+            .distinguished_name
+            .push(DnType::CommonName, "billing");
+        let client_cert = issue(client_params, Some((&ca.cert, &ca.key_pair)));
+
+        let (server_handler, _server_rx) = handler(Agent::Server);
+        let (tx, rx) = channel();
+        *server_handler.opened.lock().unwrap() = Some(tx);
+
+        let mut server = WSServerListener::init(0, server_handler).unwrap();
+        server.set_tls(config);
+        (server, rx, ca, client_cert)
+    }
+
+    #[test]
+    fn test_mtls_peer_identity() {
+        let (server, opened_rx, ca, client_cert) = mtls_server();
+        let url = format!("wss://127.0.0.1:{}/", server.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            let _client = server.accept().unwrap();
+        });
+
+        let (client_handler, _client_rx) = handler(Agent::Client);
+        let config = client_config_with_cert_from_pem(
+            ca.cert.pem().as_bytes(),
+            client_cert.cert.pem().as_bytes(),
+            client_cert.key_pair.serialize_pem().as_bytes(),
+        )
+        .unwrap();
+        let client = WSClient::connect_tls(&url, client_handler, config).unwrap();
+
+        let info = opened_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let identity = info.peer_identity.unwrap();
+        assert_eq!(identity.subject, "CN=billing");
+        assert_eq!(identity.sans, vec!["billing.mesh.internal".to_string()]);
+        assert_eq!(identity.fingerprint.len(), 64);
+        assert!(info.peer_addr.is_some());
+
+        // the client sees the verified server certificate too
+        let server_identity = client.ws_stream.info().peer_identity.as_ref().unwrap();
+        assert_eq!(server_identity.sans, vec!["127.0.0.1".to_string()]);
+    }
+
+    #[test]
+    fn test_mtls_client_without_certificate_is_rejected() {
+        let (server, opened_rx, ca, _client_cert) = mtls_server();
+        let url = format!("wss://127.0.0.1:{}/", server.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            let _ = server.accept();
+        });
+
+        let (client_handler, _client_rx) = handler(Agent::Client);
+        let config = client_config_from_pem(ca.cert.pem().as_bytes()).unwrap();
+        assert!(WSClient::connect_tls(&url, client_handler, config).is_err());
+        assert!(opened_rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_pem() {
+        let cert = generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let cert_pem = cert.cert.pem();
+        let key_pem = cert.key_pair.serialize_pem();
+
+        let err = |res: Result<_, String>| res.err().unwrap();
+        assert_eq!(
+            err(server_config_from_pem(b"", key_pem.as_bytes())),
+            "No certificate found in PEM"
+        );
+        assert!(
+            err(server_config_from_pem(cert_pem.as_bytes(), b"")).starts_with("Invalid key PEM")
+        );
+        assert_eq!(
+            err(server_config_mtls_from_pem(
+                cert_pem.as_bytes(),
+                key_pem.as_bytes(),
+                b"not a certificate",
+            )),
+            "No certificate found in PEM"
+        );
+        assert_eq!(
+            client_config_from_pem(b"").err().unwrap(),
+            "No certificate found in PEM"
+        );
+    }
+}